@@ -0,0 +1,121 @@
+//! decode.rs
+//! Executing with the cache of decoded instructions and with the block engine, against fetching
+//! and decoding each time.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use ferret_8::assembler::assemble;
+use ferret_8::decoder::decode;
+use ferret_8::emulator::{Emulator, Quirks};
+use ferret_8::engine::{Engine, Executor};
+use ferret_8::rng::SeededRng;
+use ferret_8::scheduler::Scheduler;
+use ferret_8::timing::Timing;
+use std::hint::black_box;
+
+/// Instructions executed in each iteration.
+const INSTRUCTIONS: usize = 100_000;
+
+/// A tight loop of arithmetic, skips, memory and jumps, without drawing.
+const TIGHT_LOOP: &str = "
+start:  LD V0, 0
+        LD I, buffer
+loop:   ADD V0, 1
+        LD V1, V0
+        AND V1, V2
+        XOR V3, V1
+        SHR V3, V3
+        SE V0, 0xFF
+        JP loop
+        LD [I], V3
+        LD V3, [I]
+        JP start
+buffer: DB 0, 0, 0, 0
+";
+
+/// Instructions in the body of the long program.
+const PROGRAM_LEN: usize = 1000;
+
+/// A long loop of arithmetic and skips in a pseudorandom order, without drawing nor memory
+/// access, so the decoding is not as predictable as in a tight loop.
+fn long_program() -> String {
+  let mut source = String::from("start:\n");
+  let mut seed: u32 = 1;
+  for _ in 0..PROGRAM_LEN {
+    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+    let r = seed >> 8;
+    let (x, y, n) = (r % 15, (r >> 4) % 15, (r >> 8) & 0xFF);
+    let line = match (r >> 16) % 10 {
+      | 0 => format!("ADD V{:X}, {}", x, n),
+      | 1 => format!("LD V{:X}, V{:X}", x, y),
+      | 2 => format!("OR V{:X}, V{:X}", x, y),
+      | 3 => format!("AND V{:X}, V{:X}", x, y),
+      | 4 => format!("XOR V{:X}, V{:X}", x, y),
+      | 5 => format!("SUB V{:X}, V{:X}", x, y),
+      | 6 => format!("SHR V{:X}, V{:X}", x, y),
+      | 7 => format!("SE V{:X}, {}", x, n),
+      | 8 => format!("LD V{:X}, {}", x, n),
+      | _ => format!("ADD V{:X}, V{:X}", x, y),
+    };
+    source.push_str(&line);
+    source.push('\n');
+  }
+  source.push_str("JP start\n");
+  source
+}
+
+fn emulator(source: &str) -> Emulator {
+  let mut emu = Emulator::new(Quirks::default());
+  emu.load_program(&assemble(source).unwrap()).unwrap();
+  emu
+}
+
+fn bench_decode(c: &mut Criterion) {
+  bench_program(c, "tight loop", TIGHT_LOOP);
+  bench_program(c, "long program", &long_program());
+}
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str) {
+  let mut group = c.benchmark_group(name);
+  let keys = [false; 16];
+
+  group.bench_function("fetch and decode", |b| {
+    let mut emu = emulator(source);
+    let mut rng = SeededRng::new(0);
+    b.iter(|| {
+      for _ in 0..INSTRUCTIONS {
+        let instr = decode(emu.fetch().unwrap()).unwrap();
+        emu.execute(black_box(instr), &mut rng, &keys).unwrap();
+      }
+    })
+  });
+
+  group.bench_function("decode cache", |b| {
+    let mut emu = emulator(source);
+    let mut rng = SeededRng::new(0);
+    b.iter(|| {
+      for _ in 0..INSTRUCTIONS {
+        let instr = emu.fetch_decoded().unwrap();
+        emu.execute(black_box(instr), &mut rng, &keys).unwrap();
+      }
+    })
+  });
+
+  group.bench_function("block engine", |b| {
+    let mut emu = emulator(source);
+    let mut rng = SeededRng::new(0);
+    let mut executor = Executor::new(Engine::Block);
+    // A tick with a budget of INSTRUCTIONS.
+    let mut scheduler = Scheduler::new(INSTRUCTIONS * 60, Timing::Flat);
+    b.iter(|| {
+      scheduler.start_tick();
+      while scheduler.has_budget() {
+        executor.step(&mut emu, &mut rng, &keys, &mut scheduler).unwrap();
+      }
+    })
+  });
+
+  group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
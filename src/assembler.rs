@@ -0,0 +1,339 @@
+//! assembler.rs
+//! Assembler for the mnemonic syntax produced by the disassembler.
+//!
+//! Each line can have a label, an instruction or directive, and a comment:
+//! ```text
+//! ; Draw the digit 5 in the top left corner.
+//! SPRITE_X = 0x00
+//! start:  LD V0, SPRITE_X   ; constants and labels can be used as values.
+//!         LD V1, 0
+//!         LD V2, 5
+//!         LD F, V2
+//!         DRW V0, V1, 5
+//! loop:   JP loop
+//! data:   DB 0xF0, 0x90, 0b11110000
+//!         DW 0x1234
+//! ```
+//! Numbers can be decimal, hexadecimal (0x) or binary (0b). Mnemonics, registers and directives
+//! are case insensitive, while labels and constants are not. Constants must be defined before
+//! being used in another constant, but labels can be used before their definition.
+
+pub mod error;
+
+use std::collections::HashMap;
+
+use crate::assembler::error::AsmError;
+use crate::decoder::{Instruction, encode};
+use crate::emulator::START_ADDR;
+
+/// Mnemonics known by the assembler, to tell an unknown mnemonic from wrong operands.
+const MNEMONICS: [&str; 35] = [
+  "SYS", "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE",
+  "SNE", "SAVE", "LOAD", "LD", "ADD", "RND", "DRW", "OR", "AND", "XOR", "SUB", "SUBN", "SHR",
+  "SHL", "SKP", "SKNP", "PLANE", "AUDIO", "PITCH", "DB", "DW", "LONG",
+];
+
+/// Parsed operand, values are kept as text until the symbols are known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+  Reg(usize),
+  I,
+  IndirectI,
+  Dt,
+  St,
+  K,
+  F,
+  Hf,
+  B,
+  R,
+  Long(String),
+  Value(String),
+}
+
+/// Parsed line, without label.
+#[derive(Clone, Debug)]
+enum Statement {
+  Instr(String, Vec<Operand>),
+  Bytes(Vec<String>),
+  Words(Vec<String>),
+}
+
+impl Statement {
+  /// Number of bytes the statement takes in the binary.
+  fn size(&self) -> usize {
+    match self {
+      | Self::Instr(_, ops) if ops.iter().any(|op| matches!(op, Operand::Long(_))) => 4,
+      | Self::Instr(..) => 2,
+      | Self::Bytes(values) => values.len(),
+      | Self::Words(values) => values.len() * 2,
+    }
+  }
+}
+
+/// Assemble the source into a binary, loaded in START_ADDR.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+  let mut symbols: HashMap<String, u32> = HashMap::new();
+  let mut statements = Vec::new();
+  let mut addr = START_ADDR;
+
+  // First pass: collect symbols and statements, computing the address of each one.
+  for (n, raw_line) in source.lines().enumerate() {
+    let line_num = n + 1;
+    let mut line = raw_line.split(';').next().unwrap_or("").trim();
+    if line.is_empty() {
+      continue;
+    }
+    // Constant definition.
+    if let Some((name, value)) = line.split_once('=') {
+      let name = name.trim();
+      check_symbol(line_num, name, &symbols)?;
+      let value = resolve(line_num, value.trim(), &symbols)?;
+      symbols.insert(name.to_string(), value);
+      continue;
+    }
+    // Labels, any number of them before the statement.
+    while let Some((label, rest)) = line.split_once(':') {
+      let label = label.trim();
+      check_symbol(line_num, label, &symbols)?;
+      symbols.insert(label.to_string(), addr as u32);
+      line = rest.trim();
+    }
+    if line.is_empty() {
+      continue;
+    }
+    let statement = parse_statement(line_num, line)?;
+    addr += statement.size();
+    statements.push((line_num, statement));
+  }
+
+  // Second pass: resolve the values and encode.
+  let mut binary = Vec::new();
+  for (line_num, statement) in statements {
+    match statement {
+      | Statement::Instr(mnemonic, ops) => {
+        let instr = build(line_num, &mnemonic, &ops, &symbols)?;
+        binary.extend_from_slice(&encode(instr).to_be_bytes());
+        if let Some(Operand::Long(value)) = ops.last() {
+          let long = ranged(line_num, resolve(line_num, value, &symbols)?, 0xFFFF)?;
+          binary.extend_from_slice(&(long as u16).to_be_bytes());
+        }
+      },
+      | Statement::Bytes(values) => {
+        for value in values {
+          binary.push(ranged(line_num, resolve(line_num, &value, &symbols)?, 0xFF)? as u8);
+        }
+      },
+      | Statement::Words(values) => {
+        for value in values {
+          let word = ranged(line_num, resolve(line_num, &value, &symbols)?, 0xFFFF)?;
+          binary.extend_from_slice(&(word as u16).to_be_bytes());
+        }
+      },
+    }
+  }
+  Ok(binary)
+}
+
+/// Check the name is a valid identifier, not defined yet.
+fn check_symbol(line: usize, name: &str, symbols: &HashMap<String, u32>) -> Result<(), AsmError> {
+  let mut chars = name.chars();
+  let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+  if !valid {
+    return Err(AsmError::InvalidOperands(line, name.to_string()));
+  }
+  if symbols.contains_key(name) {
+    return Err(AsmError::DuplicateSymbol(line, name.to_string()));
+  }
+  Ok(())
+}
+
+/// Split a line in mnemonic and operands.
+fn parse_statement(line: usize, text: &str) -> Result<Statement, AsmError> {
+  let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+  let mnemonic = mnemonic.to_ascii_uppercase();
+  let args: Vec<String> = if rest.trim().is_empty() {
+    Vec::new()
+  } else {
+    rest.split(',').map(|a| a.trim().to_string()).collect()
+  };
+  match mnemonic.as_str() {
+    | "DB" | "DW" if args.is_empty() => Err(AsmError::InvalidOperands(line, mnemonic)),
+    | "DB" => Ok(Statement::Bytes(args)),
+    | "DW" => Ok(Statement::Words(args)),
+    | _ => Ok(Statement::Instr(mnemonic, args.iter().map(|a| parse_operand(a)).collect())),
+  }
+}
+
+/// Parse a single operand.
+fn parse_operand(text: &str) -> Operand {
+  let upper = text.to_ascii_uppercase();
+  match upper.as_str() {
+    | "I" => Operand::I,
+    | "[I]" => Operand::IndirectI,
+    | "DT" => Operand::Dt,
+    | "ST" => Operand::St,
+    | "K" => Operand::K,
+    | "F" => Operand::F,
+    | "HF" => Operand::Hf,
+    | "B" => Operand::B,
+    | "R" => Operand::R,
+    | _ if upper.starts_with("LONG ") => Operand::Long(text[5..].trim().to_string()),
+    | _ if upper.len() == 2 && upper.starts_with('V') => {
+      match upper[1..].chars().next().and_then(|c| c.to_digit(16)) {
+        | Some(reg) => Operand::Reg(reg as usize),
+        | None => Operand::Value(text.to_string()),
+      }
+    },
+    | _ => Operand::Value(text.to_string()),
+  }
+}
+
+/// Convert a number or symbol into its value.
+fn resolve(line: usize, text: &str, symbols: &HashMap<String, u32>) -> Result<u32, AsmError> {
+  let lower = text.to_ascii_lowercase();
+  let number = if let Some(hex) = lower.strip_prefix("0x") {
+    u32::from_str_radix(hex, 16).ok()
+  } else if let Some(bin) = lower.strip_prefix("0b") {
+    u32::from_str_radix(bin, 2).ok()
+  } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+    lower.parse().ok()
+  } else {
+    return symbols
+      .get(text)
+      .copied()
+      .ok_or_else(|| AsmError::UndefinedSymbol(line, text.to_string()));
+  };
+  number.ok_or_else(|| AsmError::InvalidNumber(line, text.to_string()))
+}
+
+/// Check the value is not bigger than max.
+fn ranged(line: usize, value: u32, max: u32) -> Result<u32, AsmError> {
+  if value > max { Err(AsmError::OutOfRange(line, value)) } else { Ok(value) }
+}
+
+/// Build the instruction from the mnemonic and its operands.
+fn build(
+  line: usize, mnemonic: &str, ops: &[Operand], symbols: &HashMap<String, u32>,
+) -> Result<Instruction, AsmError> {
+  use Operand::*;
+  let value = |text: &String, max: u32| ranged(line, resolve(line, text, symbols)?, max);
+  let instr = match (mnemonic, ops) {
+    | ("SYS", [Value(a)]) => Instruction::Sys(value(a, 0xFFF)? as usize),
+    | ("CLS", []) => Instruction::Cls,
+    | ("RET", []) => Instruction::Return,
+    | ("SCD", [Value(n)]) => Instruction::ScrollDown(value(n, 0xF)? as u8),
+    | ("SCU", [Value(n)]) => Instruction::ScrollUp(value(n, 0xF)? as u8),
+    | ("SCR", []) => Instruction::ScrollRight,
+    | ("SCL", []) => Instruction::ScrollLeft,
+    | ("EXIT", []) => Instruction::Exit,
+    | ("LOW", []) => Instruction::Lores,
+    | ("HIGH", []) => Instruction::Hires,
+    | ("JP", [Value(a)]) => Instruction::SetPC(value(a, 0xFFF)? as usize),
+    | ("JP", [Reg(0), Value(a)]) => Instruction::Jump(value(a, 0xFFF)? as usize),
+    | ("CALL", [Value(a)]) => Instruction::Call(value(a, 0xFFF)? as usize),
+    | ("SE", [Reg(x), Value(n)]) => Instruction::SeInmm(*x, value(n, 0xFF)? as u8),
+    | ("SE", [Reg(x), Reg(y)]) => Instruction::SeReg(*x, *y),
+    | ("SNE", [Reg(x), Value(n)]) => Instruction::SneInmm(*x, value(n, 0xFF)? as u8),
+    | ("SNE", [Reg(x), Reg(y)]) => Instruction::SneReg(*x, *y),
+    | ("SAVE", [Reg(x), Reg(y)]) => Instruction::StRange(*x, *y),
+    | ("LOAD", [Reg(x), Reg(y)]) => Instruction::LdRange(*x, *y),
+    | ("LD", [Reg(x), Value(n)]) => Instruction::LoadInmm(*x, value(n, 0xFF)? as u8),
+    | ("LD", [Reg(x), Reg(y)]) => Instruction::LoadReg(*x, *y),
+    | ("LD", [I, Value(a)]) => Instruction::LoadI(value(a, 0xFFF)? as usize),
+    | ("LD", [I, Long(_)]) => Instruction::LoadLongI,
+    | ("LD", [Reg(x), Dt]) => Instruction::GetDelay(*x),
+    | ("LD", [Reg(x), K]) => Instruction::WaitKey(*x),
+    | ("LD", [Dt, Reg(x)]) => Instruction::LoadDelay(*x),
+    | ("LD", [St, Reg(x)]) => Instruction::LoadSound(*x),
+    | ("LD", [F, Reg(x)]) => Instruction::LoadFont(*x),
+    | ("LD", [Hf, Reg(x)]) => Instruction::LoadBigFont(*x),
+    | ("LD", [B, Reg(x)]) => Instruction::Bcd(*x),
+    | ("LD", [IndirectI, Reg(x)]) => Instruction::StMem(*x),
+    | ("LD", [Reg(x), IndirectI]) => Instruction::LdMem(*x),
+    | ("LD", [R, Reg(x)]) => Instruction::StFlags(*x),
+    | ("LD", [Reg(x), R]) => Instruction::LdFlags(*x),
+    | ("ADD", [Reg(x), Value(n)]) => Instruction::Sum(*x, value(n, 0xFF)? as u8),
+    | ("ADD", [Reg(x), Reg(y)]) => Instruction::Add(*x, *y),
+    | ("ADD", [I, Reg(x)]) => Instruction::AddI(*x),
+    | ("RND", [Reg(x), Value(n)]) => Instruction::Rand(*x, value(n, 0xFF)? as u8),
+    | ("DRW", [Reg(x), Reg(y), Value(n)]) => Instruction::Display(*x, *y, value(n, 0xF)? as u8),
+    | ("OR", [Reg(x), Reg(y)]) => Instruction::Or(*x, *y),
+    | ("AND", [Reg(x), Reg(y)]) => Instruction::And(*x, *y),
+    | ("XOR", [Reg(x), Reg(y)]) => Instruction::Xor(*x, *y),
+    | ("SUB", [Reg(x), Reg(y)]) => Instruction::Sub(*x, *y),
+    | ("SUBN", [Reg(x), Reg(y)]) => Instruction::SubRev(*x, *y),
+    | ("SHR", [Reg(x)]) => Instruction::ShiftRight(*x, *x),
+    | ("SHR", [Reg(x), Reg(y)]) => Instruction::ShiftRight(*x, *y),
+    | ("SHL", [Reg(x)]) => Instruction::ShiftLeft(*x, *x),
+    | ("SHL", [Reg(x), Reg(y)]) => Instruction::ShiftLeft(*x, *y),
+    | ("SKP", [Reg(x)]) => Instruction::Skip(*x),
+    | ("SKNP", [Reg(x)]) => Instruction::Snkip(*x),
+    | ("PLANE", [Value(n)]) => Instruction::Plane(value(n, 0x3)? as u8),
+    | ("AUDIO", []) => Instruction::LoadAudio,
+    | ("PITCH", [Reg(x)]) => Instruction::Pitch(*x),
+    | _ if MNEMONICS.contains(&mnemonic) => {
+      return Err(AsmError::InvalidOperands(line, mnemonic.to_string()));
+    },
+    | _ => return Err(AsmError::UnknownMnemonic(line, mnemonic.to_string())),
+  };
+  Ok(instr)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::assembler::{assemble, error::AsmError};
+  use crate::disasm::disassemble;
+
+  #[test]
+  fn test_assemble() {
+    let source = "
+      ; Draw the digit 5.
+      DIGIT = 5
+      start:  LD V0, 0x00
+              LD V1, 0
+              LD V2, DIGIT
+              LD F, V2
+              DRW V0, V1, 5
+      loop:   JP loop
+      data:   db 0xF0, 0b10010000
+              dw data
+    ";
+    let binary = assemble(source).unwrap();
+    assert_eq!(
+      binary,
+      [
+        0x60, 0x00, 0x61, 0x00, 0x62, 0x05, 0xF2, 0x29, 0xD0, 0x15, 0x12, 0x0A, 0xF0, 0x90, 0x02,
+        0x0C
+      ]
+    );
+  }
+
+  #[test]
+  fn test_forward_label_and_long() {
+    let binary = assemble("LD I, LONG end\nCALL end\nend: RET").unwrap();
+    assert_eq!(binary, [0xF0, 0x00, 0x02, 0x06, 0x22, 0x06, 0x00, 0xEE]);
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_eq!(assemble("FOO V1"), Err(AsmError::UnknownMnemonic(1, "FOO".to_string())));
+    assert_eq!(assemble("CLS\nLD V1"), Err(AsmError::InvalidOperands(2, "LD".to_string())));
+    assert_eq!(assemble("JP nowhere"), Err(AsmError::UndefinedSymbol(1, "nowhere".to_string())));
+    assert_eq!(assemble("a: CLS\na: CLS"), Err(AsmError::DuplicateSymbol(2, "a".to_string())));
+    assert_eq!(assemble("LD V0, 256"), Err(AsmError::OutOfRange(1, 256)));
+    assert_eq!(assemble("LD V0, 0xZZ"), Err(AsmError::InvalidNumber(1, "0xZZ".to_string())));
+  }
+
+  /// The listing of the disassembler must assemble back to the same binary.
+  #[test]
+  fn test_disassembler_roundtrip() {
+    let program = [
+      0x00, 0xE0, 0x00, 0xC3, 0x00, 0xFF, 0x12, 0x34, 0x5A, 0xB2, 0x8A, 0xBE, 0xB2, 0x00, 0xC1,
+      0x7F, 0xD1, 0x20, 0xF0, 0x00, 0xAB, 0xCD, 0xF2, 0x01, 0xF3, 0x0A, 0xF4, 0x75, 0xF4, 0x85,
+      0xFF, 0xFF, 0x42,
+    ];
+    let source: Vec<String> = disassemble(&program, 0x200).iter().map(|l| l.text.clone()).collect();
+    assert_eq!(assemble(&source.join("\n")).unwrap(), program);
+  }
+}
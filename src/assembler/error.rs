@@ -0,0 +1,29 @@
+//! error.rs
+//! Assembler errors, each one with the line (starting in 1) where it happened.
+
+use std::{error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmError {
+  UnknownMnemonic(usize, String),
+  InvalidOperands(usize, String),
+  InvalidNumber(usize, String),
+  UndefinedSymbol(usize, String),
+  DuplicateSymbol(usize, String),
+  OutOfRange(usize, u32),
+}
+
+impl fmt::Display for AsmError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::UnknownMnemonic(l, s) => write!(f, "Line {}: unknown mnemonic {}", l, s),
+      | Self::InvalidOperands(l, s) => write!(f, "Line {}: invalid operands for {}", l, s),
+      | Self::InvalidNumber(l, s) => write!(f, "Line {}: invalid number {}", l, s),
+      | Self::UndefinedSymbol(l, s) => write!(f, "Line {}: undefined symbol {}", l, s),
+      | Self::DuplicateSymbol(l, s) => write!(f, "Line {}: symbol {} already defined", l, s),
+      | Self::OutOfRange(l, n) => write!(f, "Line {}: value out of range 0x{:X}", l, n),
+    }
+  }
+}
+
+impl Error for AsmError {}
@@ -0,0 +1,168 @@
+//! audio.rs
+//! Sound generation for the sound timer, independent of any audio device.
+
+use std::f32::consts::TAU;
+
+use crate::emulator::AUDIO_PATTERN_SIZE;
+
+/// Bits (1-bit samples) in the XO-CHIP audio pattern.
+const PATTERN_BITS: usize = AUDIO_PATTERN_SIZE * 8;
+
+/// Shape of the tone played by the beeper.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Waveform {
+  #[default]
+  Square,
+  Sine,
+  Triangle,
+  Sawtooth,
+}
+
+/// The CHIP-8 only has a buzzer, sounding while the sound timer is above 0. XO-CHIP programs can
+/// load instead an audio pattern, played in a loop at the rate set by the pitch.
+///
+/// The beeper renders that tone (or the pattern) into sample buffers (mono, f32 in the range -1.0
+/// to 1.0), so the same samples can be sent to an audio device by the frontend, or checked in a
+/// test.
+///
+/// The phase is kept between calls to render, so consecutive buffers join without clicks.
+/// When the sound stops, the phase goes back to 0, so every beep starts in the same way.
+#[derive(Clone, Debug)]
+pub struct Beeper {
+  sample_rate: u32,
+  frequency: f32,
+  waveform: Waveform,
+  volume: f32,
+  /// XO-CHIP audio pattern and pitch, played instead of the tone if set.
+  pattern: Option<([u8; AUDIO_PATTERN_SIZE], u8)>,
+  /// Position inside the current period (or pattern), from 0.0 to 1.0.
+  phase: f32,
+}
+
+impl Beeper {
+  /// Creates a new beeper. Volume is clamped between 0.0 and 1.0.
+  pub fn new(sample_rate: u32, frequency: f32, waveform: Waveform, volume: f32) -> Self {
+    debug_assert!(sample_rate > 0);
+    let volume = volume.clamp(0.0, 1.0);
+    Self { sample_rate, frequency, waveform, volume, pattern: None, phase: 0.0 }
+  }
+
+  /// Samples per second the beeper renders.
+  pub fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  /// Play the XO-CHIP audio pattern with the pitch instead of the tone, or the tone with None.
+  pub fn set_pattern(&mut self, pattern: Option<&[u8; AUDIO_PATTERN_SIZE]>, pitch: u8) {
+    self.pattern = pattern.map(|pattern| (*pattern, pitch));
+  }
+
+  /// Fill the buffer with the next samples. If active is false, the buffer is filled with silence.
+  pub fn render(&mut self, active: bool, buffer: &mut [f32]) {
+    if !active {
+      buffer.fill(0.0);
+      self.phase = 0.0;
+      return;
+    }
+    // Periods per second: of the whole pattern, or of the tone.
+    let frequency = match self.pattern {
+      | Some((_, pitch)) => pattern_rate(pitch) / PATTERN_BITS as f32,
+      | None => self.frequency,
+    };
+    let step = frequency / self.sample_rate as f32;
+    for sample in buffer.iter_mut() {
+      *sample = self.volume * self.value(self.phase);
+      self.phase = (self.phase + step).fract();
+    }
+  }
+
+  /// Value of the pattern, or else the waveform, in the phase p (0.0 to 1.0).
+  fn value(&self, p: f32) -> f32 {
+    let Some((pattern, _)) = &self.pattern else {
+      return self.wave(p);
+    };
+    let bit = ((p * PATTERN_BITS as f32) as usize).min(PATTERN_BITS - 1);
+    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+  }
+
+  /// Value of the waveform in the phase p (0.0 to 1.0).
+  fn wave(&self, p: f32) -> f32 {
+    match self.waveform {
+      | Waveform::Square => {
+        if p < 0.5 {
+          1.0
+        } else {
+          -1.0
+        }
+      },
+      | Waveform::Sine => (p * TAU).sin(),
+      | Waveform::Triangle => 1.0 - 4.0 * (p - 0.5).abs(),
+      | Waveform::Sawtooth => 2.0 * p - 1.0,
+    }
+  }
+}
+
+/// Bits per second the XO-CHIP audio pattern is played at with the pitch:
+/// 4000 * 2^((pitch - 64) / 48).
+pub fn pattern_rate(pitch: u8) -> f32 {
+  4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::audio::{Beeper, Waveform, pattern_rate};
+
+  #[test]
+  fn test_silence() {
+    let mut beeper = Beeper::new(8000, 440.0, Waveform::Square, 0.5);
+    let mut buffer = [1.0; 64];
+    beeper.render(false, &mut buffer);
+    assert!(buffer.iter().all(|s| *s == 0.0));
+  }
+
+  #[test]
+  fn test_volume() {
+    for waveform in [Waveform::Square, Waveform::Sine, Waveform::Triangle, Waveform::Sawtooth] {
+      let mut beeper = Beeper::new(44100, 440.0, waveform, 0.25);
+      let mut buffer = [0.0; 1024];
+      beeper.render(true, &mut buffer);
+      assert!(buffer.iter().all(|s| s.abs() <= 0.25));
+      assert!(buffer.iter().any(|s| s.abs() > 0.2));
+    }
+  }
+
+  #[test]
+  fn test_square_period() {
+    // 1000Hz at 8000 samples per second: 4 samples high, 4 samples low.
+    let mut beeper = Beeper::new(8000, 1000.0, Waveform::Square, 1.0);
+    let mut first = [0.0; 6];
+    let mut second = [0.0; 10];
+    beeper.render(true, &mut first);
+    beeper.render(true, &mut second);
+    let samples: Vec<f32> = first.iter().chain(second.iter()).copied().collect();
+    let expected = [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0];
+    assert_eq!(samples[..8], expected);
+    assert_eq!(samples[8..], expected);
+  }
+
+  #[test]
+  fn test_pattern() {
+    assert_eq!(pattern_rate(64), 4000.0);
+    assert_eq!(pattern_rate(112), 8000.0);
+    // 4000 bits per second at 8000 samples per second: 2 samples per bit.
+    let mut beeper = Beeper::new(8000, 440.0, Waveform::Sine, 1.0);
+    let mut pattern = [0; 16];
+    pattern[0] = 0xF0;
+    beeper.set_pattern(Some(&pattern), 64);
+    let mut buffer = [0.0; 512];
+    beeper.render(true, &mut buffer);
+    let expected: Vec<f32> = (0..256).map(|n| if n < 8 { 1.0 } else { -1.0 }).collect();
+    assert_eq!(buffer[..256], expected);
+    assert_eq!(buffer[256..], expected);
+
+    // Back to the tone.
+    beeper.set_pattern(None, 64);
+    beeper.render(true, &mut buffer);
+    assert!(buffer.iter().any(|s| s.abs() < 0.9));
+  }
+}
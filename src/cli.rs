@@ -3,35 +3,308 @@
 
 use std::sync::RwLock;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use ferret_8::audio::Waveform;
+use ferret_8::debugger::Breakpoint;
+use ferret_8::emulator::Quirks;
+use ferret_8::engine::Engine;
+use ferret_8::fault::{FaultPolicy, FaultRule};
+use ferret_8::keymap::Layout;
+use ferret_8::timing::Timing;
 
 pub static PROGRAM_NAME: RwLock<String> = RwLock::new(String::new());
 pub static CYCLES: RwLock<usize> = RwLock::new(0);
 pub static UPSCALE_FACTOR: RwLock<usize> = RwLock::new(0);
-pub static COMPATIBILITY: RwLock<bool> = RwLock::new(true);
+pub static QUIRKS: RwLock<Quirks> = RwLock::new(Quirks::COSMAC_VIP);
+pub static TONE: RwLock<f32> = RwLock::new(0.0);
+pub static WAVEFORM: RwLock<Waveform> = RwLock::new(Waveform::Square);
+pub static VOLUME: RwLock<f32> = RwLock::new(0.0);
+pub static MUTE: RwLock<bool> = RwLock::new(false);
+pub static DEBUG: RwLock<bool> = RwLock::new(false);
+pub static BREAKPOINTS: RwLock<Vec<Breakpoint>> = RwLock::new(Vec::new());
+pub static LOAD_STATE: RwLock<Option<String>> = RwLock::new(None);
+pub static REWIND_SECONDS: RwLock<usize> = RwLock::new(0);
+pub static SEED: RwLock<Option<u64>> = RwLock::new(None);
+pub static MOVIE: RwLock<Option<String>> = RwLock::new(None);
+pub static RECORD: RwLock<Option<String>> = RwLock::new(None);
+pub static CONFIG: RwLock<Option<String>> = RwLock::new(None);
+pub static LAYOUT: RwLock<Option<Layout>> = RwLock::new(None);
+pub static TIMING: RwLock<Timing> = RwLock::new(Timing::Flat);
+pub static ENGINE: RwLock<Engine> = RwLock::new(Engine::Interp);
+pub static CRASH_REPORT: RwLock<Option<String>> = RwLock::new(None);
+pub static FAULT_POLICY: RwLock<FaultPolicy> = RwLock::new(FaultPolicy::HALT);
+
+/// Quirk presets selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Platform {
+  /// Original COSMAC VIP interpreter.
+  Vip,
+  /// CHIP-48 (HP-48).
+  Chip48,
+  /// SUPER-CHIP 1.1.
+  Schip,
+  /// XO-CHIP (Octo).
+  Xochip,
+}
+
+impl From<Platform> for Quirks {
+  fn from(platform: Platform) -> Self {
+    match platform {
+      | Platform::Vip => Quirks::COSMAC_VIP,
+      | Platform::Chip48 => Quirks::CHIP_48,
+      | Platform::Schip => Quirks::SCHIP,
+      | Platform::Xochip => Quirks::XO_CHIP,
+    }
+  }
+}
+
+/// Waveforms selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum WaveShape {
+  Square,
+  Sine,
+  Triangle,
+  Sawtooth,
+}
+
+impl From<WaveShape> for Waveform {
+  fn from(shape: WaveShape) -> Self {
+    match shape {
+      | WaveShape::Square => Waveform::Square,
+      | WaveShape::Sine => Waveform::Sine,
+      | WaveShape::Triangle => Waveform::Triangle,
+      | WaveShape::Sawtooth => Waveform::Sawtooth,
+    }
+  }
+}
+
+/// Keyboard layouts selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum KeyLayout {
+  Qwerty,
+  Azerty,
+  Qwertz,
+  Dvorak,
+  Numpad,
+}
+
+impl From<KeyLayout> for Layout {
+  fn from(layout: KeyLayout) -> Self {
+    match layout {
+      | KeyLayout::Qwerty => Layout::Qwerty,
+      | KeyLayout::Azerty => Layout::Azerty,
+      | KeyLayout::Qwertz => Layout::Qwertz,
+      | KeyLayout::Dvorak => Layout::Dvorak,
+      | KeyLayout::Numpad => Layout::Numpad,
+    }
+  }
+}
+
+/// Timing models selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum TimingModel {
+  /// --cycles instructions per second, all of them taking the same time.
+  Flat,
+  /// Each instruction takes the time it took in the COSMAC VIP (ignores --cycles).
+  Vip,
+}
+
+impl From<TimingModel> for Timing {
+  fn from(model: TimingModel) -> Self {
+    match model {
+      | TimingModel::Flat => Timing::Flat,
+      | TimingModel::Vip => Timing::Vip,
+    }
+  }
+}
+
+/// Execution engines selectable from the command line.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum EngineKind {
+  /// Fetch, decode and execute each instruction.
+  Interp,
+  /// Run straight-line blocks of instructions decoded in advance (faster).
+  Block,
+}
+
+impl From<EngineKind> for Engine {
+  fn from(kind: EngineKind) -> Self {
+    match kind {
+      | EngineKind::Interp => Engine::Interp,
+      | EngineKind::Block => Engine::Block,
+    }
+  }
+}
+
+/// Formats of the display dump in headless mode.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum ScreenFormat {
+  #[default]
+  Ascii,
+  Pbm,
+  Png,
+}
+
+/// Tools that work with a program, or run it in other ways.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Run the program (same as --program), or without window with --headless.
+  Run {
+    /// Program path.
+    program: String,
+    /// Run without window, input and sound, and dump the final state.
+    #[arg(long)]
+    headless: bool,
+    /// Frames (1/60 s) to run in headless mode [default: 600, or the length of the movie].
+    #[arg(long)]
+    frames: Option<u64>,
+    /// Script with the keys held in each frame, in headless mode.
+    #[arg(long, value_name = "PATH")]
+    keys: Option<String>,
+    /// File to write the final display to. By default, it is printed.
+    #[arg(long, value_name = "PATH")]
+    screen: Option<String>,
+    /// Format of the final display.
+    #[arg(long, value_enum, default_value_t = ScreenFormat::Ascii)]
+    format: ScreenFormat,
+    /// File to write the final registers to, as JSON. By default, they are printed.
+    #[arg(long, value_name = "PATH")]
+    registers: Option<String>,
+  },
+  /// Print an address annotated listing of the program.
+  Disasm {
+    /// Program path.
+    program: String,
+  },
+  /// Assemble a source file into a program.
+  Asm {
+    /// Source path.
+    source: String,
+    /// Output path. By default, the source path with the .ch8 extension.
+    #[arg(short, long)]
+    output: Option<String>,
+  },
+}
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+  #[command(subcommand)]
+  command: Option<Command>,
   /// Program path.
-  #[arg(short, long)]
-  program: String,
+  #[arg(short, long, required = true)]
+  program: Option<String>,
   /// Cycles (instructions) per second the program will execute.
-  #[arg(short, long, default_value_t = 700)]
+  #[arg(short, long, global = true, default_value_t = 700)]
   cycles: usize,
   /// Upscale factor from the original 64x32 pixel size.
   #[arg(short, long, default_value_t = 20)]
   upscale_factor: usize,
-  /// Modern behaviour in a some instructions.
+  /// Timing of the instructions.
+  #[arg(long, global = true, value_enum, default_value_t = TimingModel::Flat)]
+  timing: TimingModel,
+  /// Engine executing the program. The debugger panel always uses the interpreter.
+  #[arg(long, global = true, value_enum, default_value_t = EngineKind::Interp)]
+  engine: EngineKind,
+  /// Platform whose quirks (behaviour in some instructions) will be emulated.
+  #[arg(short, long, global = true, value_enum, default_value_t = Platform::Vip)]
+  quirks: Platform,
+  /// Modern behaviour in some instructions, same as --quirks chip48.
+  #[arg(short, long, global = true, conflicts_with = "quirks")]
+  modern_compatibility: bool,
+  /// Fx1E sets VF when I goes beyond 0xFFF, as some games expect, with any --quirks.
+  #[arg(long, global = true)]
+  add_i_overflow: bool,
+  /// Frequency (Hz) of the tone played while the sound timer is active, unless the program loaded
+  /// an XO-CHIP audio pattern.
+  #[arg(short, long, default_value_t = 440.0)]
+  tone: f32,
+  /// Waveform of the tone.
+  #[arg(short, long, value_enum, default_value_t = WaveShape::Square)]
+  waveform: WaveShape,
+  /// Volume of the tone, from 0.0 to 1.0.
+  #[arg(long, default_value_t = 0.25)]
+  volume: f32,
+  /// Disable the sound.
+  #[arg(long)]
+  mute: bool,
+  /// Start paused, with the debugger panel (F5 continue/pause, F10 step, F9 breakpoint).
   #[arg(short, long)]
-  modern_compatibility: bool
+  debug: bool,
+  /// Breakpoint in a program address (hex). Can be repeated.
+  #[arg(long = "break", value_name = "ADDR", value_parser = Breakpoint::parse_address)]
+  break_addr: Vec<Breakpoint>,
+  /// Breakpoint in an opcode pattern, with ? as wildcard nibble (e.g. D??0). Can be repeated.
+  #[arg(long = "break-op", value_name = "PATTERN", value_parser = Breakpoint::parse_opcode)]
+  break_op: Vec<Breakpoint>,
+  /// Save state to restore before running the program.
+  #[arg(long, global = true, value_name = "PATH")]
+  load_state: Option<String>,
+  /// Seconds of history kept to rewind (holding Backspace). 0 disables it.
+  #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+  rewind: usize,
+  /// Seed of the random generator, to reproduce a run. By default, a random one.
+  #[arg(long, global = true)]
+  seed: Option<u64>,
+  /// Play back an input movie, with the quirks, seed, cycles, timing and fault policy it was
+  /// recorded with.
+  #[arg(
+    long,
+    global = true,
+    value_name = "PATH",
+    conflicts_with_all = ["record", "load_state", "on_fault"]
+  )]
+  movie: Option<String>,
+  /// Record the keys of each frame to an input movie.
+  #[arg(long, global = true, value_name = "PATH", conflicts_with = "load_state")]
+  record: Option<String>,
+  /// Config file with the keyboard layout and key bindings (TOML).
+  #[arg(long, global = true, value_name = "PATH")]
+  config: Option<String>,
+  /// Keyboard layout. Replaces the one in the config file.
+  #[arg(short, long, global = true, value_enum)]
+  layout: Option<KeyLayout>,
+  /// Write the crash report to a file if the program faults.
+  #[arg(long, global = true, value_name = "PATH")]
+  crash_report: Option<String>,
+  /// Action on a kind of fault (unknown-opcode, invalid-address, stack, unknown-key or all):
+  /// halt, pause, nop or wrap. Can be repeated.
+  #[arg(long, global = true, value_name = "CLASS=ACTION", value_parser = FaultRule::parse)]
+  on_fault: Vec<FaultRule>,
 }
 
 /// Parse the command arguments of the program.
-pub fn parse_arguments() {
+/// Return the subcommand, if any. Otherwise the program will be run.
+pub fn parse_arguments() -> Option<Command> {
   let args = Args::parse();
-  *PROGRAM_NAME.try_write().unwrap() = args.program;
+  *PROGRAM_NAME.try_write().unwrap() = args.program.unwrap_or_default();
   *CYCLES.try_write().unwrap() = args.cycles;
   *UPSCALE_FACTOR.try_write().unwrap() = args.upscale_factor;
-  *COMPATIBILITY.try_write().unwrap() = args.modern_compatibility;
+  let platform = if args.modern_compatibility { Platform::Chip48 } else { args.quirks };
+  *QUIRKS.try_write().unwrap() =
+    Quirks { add_i_overflow: args.add_i_overflow, ..Quirks::from(platform) };
+  *TIMING.try_write().unwrap() = args.timing.into();
+  *ENGINE.try_write().unwrap() = args.engine.into();
+  *TONE.try_write().unwrap() = args.tone;
+  *WAVEFORM.try_write().unwrap() = args.waveform.into();
+  *VOLUME.try_write().unwrap() = args.volume;
+  *MUTE.try_write().unwrap() = args.mute;
+  *DEBUG.try_write().unwrap() = args.debug;
+  let mut breakpoints = args.break_addr;
+  breakpoints.extend(args.break_op);
+  *BREAKPOINTS.try_write().unwrap() = breakpoints;
+  *LOAD_STATE.try_write().unwrap() = args.load_state;
+  *REWIND_SECONDS.try_write().unwrap() = args.rewind;
+  *SEED.try_write().unwrap() = args.seed;
+  *MOVIE.try_write().unwrap() = args.movie;
+  *RECORD.try_write().unwrap() = args.record;
+  *CONFIG.try_write().unwrap() = args.config;
+  *LAYOUT.try_write().unwrap() = args.layout.map(Layout::from);
+  *CRASH_REPORT.try_write().unwrap() = args.crash_report;
+  let mut policy = FaultPolicy::HALT;
+  for rule in args.on_fault.iter() {
+    policy.apply(rule);
+  }
+  *FAULT_POLICY.try_write().unwrap() = policy;
+  args.command
 }
@@ -0,0 +1,211 @@
+//! debugger.rs
+//! Breakpoints, single-step and state inspection of the emulator.
+
+pub mod error;
+
+use crate::debugger::error::BreakpointError;
+use crate::decoder::{Instruction, decode};
+use crate::emulator::Emulator;
+use crate::fault::{self, Fault};
+
+use anyhow::Result;
+use rand::prelude::*;
+
+/// Condition to pause the execution, checked before executing each instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+  /// Pause when the program counter reaches the address.
+  Address(usize),
+  /// Pause when the next opcode, masked, is equal to value.
+  Opcode { value: u16, mask: u16 },
+}
+
+impl Breakpoint {
+  /// Parse an hexadecimal address, with or without the 0x prefix.
+  pub fn parse_address(s: &str) -> Result<Self, BreakpointError> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16)
+      .map(Self::Address)
+      .map_err(|_| BreakpointError::InvalidAddress(s.to_string()))
+  }
+
+  /// Parse an opcode pattern of four nibbles, with or without the 0x prefix.
+  /// Each nibble is an hexadecimal digit or a wildcard (?, x, y or n). Example: D??0, 8xy6.
+  pub fn parse_opcode(s: &str) -> Result<Self, BreakpointError> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    if digits.chars().count() != 4 {
+      return Err(BreakpointError::InvalidPattern(s.to_string()));
+    }
+    let mut value = 0;
+    let mut mask = 0;
+    for c in digits.chars() {
+      value <<= 4;
+      mask <<= 4;
+      match c {
+        | '?' | 'x' | 'y' | 'n' => {},
+        | _ => {
+          let nibble =
+            c.to_digit(16).ok_or_else(|| BreakpointError::InvalidPattern(s.to_string()))?;
+          value |= nibble as u16;
+          mask |= 0xF;
+        },
+      }
+    }
+    Ok(Self::Opcode { value, mask })
+  }
+
+  /// Return true if the breakpoint matches the program counter and the opcode it points.
+  pub fn matches(&self, pc: usize, opcode: u16) -> bool {
+    match *self {
+      | Self::Address(addr) => addr == pc,
+      | Self::Opcode { value, mask } => opcode & mask == value,
+    }
+  }
+}
+
+/// Keeps the breakpoints and whether the execution is paused.
+///
+/// The frontend asks check before each instruction, and stops running cycles while paused.
+/// When resumed, the first instruction is not checked, so a breakpoint doesn't stop twice.
+#[derive(Debug, Default)]
+pub struct Debugger {
+  breakpoints: Vec<Breakpoint>,
+  paused: bool,
+  skip_check: bool,
+}
+
+impl Debugger {
+  /// Creates a new debugger, paused, so the program can be inspected before running.
+  pub fn new() -> Self {
+    Self { paused: true, ..Self::default() }
+  }
+
+  /// Add a new breakpoint.
+  pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+    self.breakpoints.push(breakpoint);
+  }
+
+  /// Add an address breakpoint, or remove it if already present.
+  pub fn toggle_address(&mut self, addr: usize) {
+    let bp = Breakpoint::Address(addr);
+    if let Some(pos) = self.breakpoints.iter().position(|b| *b == bp) {
+      self.breakpoints.remove(pos);
+    } else {
+      self.breakpoints.push(bp);
+    }
+  }
+
+  /// Current breakpoints.
+  pub fn breakpoints(&self) -> &[Breakpoint] {
+    &self.breakpoints
+  }
+
+  /// Return true if the execution is paused.
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  /// Pause the execution.
+  pub fn pause(&mut self) {
+    self.paused = true;
+  }
+
+  /// Resume the execution.
+  pub fn resume(&mut self) {
+    self.paused = false;
+    self.skip_check = true;
+  }
+
+  /// Return true (and pause) if any breakpoint matches the next instruction of the emulator.
+  pub fn check(&mut self, emu: &Emulator) -> bool {
+    if self.skip_check {
+      self.skip_check = false;
+      return false;
+    }
+    let pc = emu.reg_pc();
+    let opcode = emu.opcode_at(pc).unwrap_or(0);
+    if self.breakpoints.iter().any(|b| b.matches(pc, opcode)) {
+      self.paused = true;
+    }
+    self.paused
+  }
+
+  /// Fetch, decode and execute a single instruction, returning it.
+  pub fn step<R: Rng + ?Sized>(
+    &mut self, emu: &mut Emulator, rng: &mut R, keys: &[bool],
+  ) -> Result<Instruction, Fault> {
+    fault::step(emu, rng, keys)
+  }
+
+  /// Lines describing the state of the emulator: registers, timers, next instruction and stack.
+  pub fn report(&self, emu: &Emulator) -> Vec<String> {
+    let mut lines = Vec::new();
+    let pc = emu.reg_pc();
+    let state = if self.paused { "PAUSED" } else { "RUNNING" };
+    lines.push(state.to_string());
+    lines.push(format!("PC: 0x{:04X}  I: 0x{:04X}", pc, emu.reg_i()));
+    lines.push(format!("DT: {:02X}  ST: {:02X}", emu.delay_timer(), emu.sound_timer()));
+    let next = match emu.opcode_at(pc) {
+      | Some(opcode) => match decode(opcode) {
+        | Ok(instr) => format!("0x{:04X} {}", opcode, instr),
+        | Err(_) => format!("0x{:04X} ???", opcode),
+      },
+      | None => "-".to_string(),
+    };
+    lines.push(format!("NEXT: {}", next));
+    let reg = emu.registers();
+    for r in 0..(reg.len() / 2) {
+      let h = r + reg.len() / 2;
+      lines.push(format!("V{:X}: {:02X}  V{:X}: {:02X}", r, reg[r], h, reg[h]));
+    }
+    let stack = emu.stack();
+    lines.push(format!("STACK ({}):", stack.depth()));
+    for (n, value) in stack.as_slice().iter().enumerate().rev() {
+      lines.push(format!("{:2}: 0x{:04X}", n, value));
+    }
+    lines
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::debugger::{Breakpoint, Debugger};
+  use crate::emulator::{Emulator, Quirks};
+
+  #[test]
+  fn test_parse_address() {
+    assert_eq!(Breakpoint::parse_address("0x2A0"), Ok(Breakpoint::Address(0x2A0)));
+    assert_eq!(Breakpoint::parse_address("300"), Ok(Breakpoint::Address(0x300)));
+    assert!(Breakpoint::parse_address("0xZZ").is_err());
+  }
+
+  #[test]
+  fn test_parse_opcode() {
+    let bp = Breakpoint::parse_opcode("D??0").unwrap();
+    assert_eq!(bp, Breakpoint::Opcode { value: 0xD000, mask: 0xF00F });
+    assert!(bp.matches(0, 0xD120));
+    assert!(!bp.matches(0, 0xD125));
+    assert!(Breakpoint::parse_opcode("8xy6").unwrap().matches(0, 0x8AB6));
+    assert!(Breakpoint::parse_opcode("D0").is_err());
+    assert!(Breakpoint::parse_opcode("G000").is_err());
+  }
+
+  #[test]
+  fn test_check_and_step() {
+    let mut emu = Emulator::new(Quirks::default());
+    emu.load_program(&[0x60, 0x05, 0x61, 0x06, 0x12, 0x00]).unwrap();
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    let mut debugger = Debugger::new();
+    debugger.add_breakpoint(Breakpoint::parse_opcode("61??").unwrap());
+    assert!(debugger.check(&emu));
+    debugger.resume();
+    while !debugger.check(&emu) {
+      debugger.step(&mut emu, &mut rng, &keys).unwrap();
+    }
+    assert_eq!(emu.reg_pc(), 0x202);
+    assert_eq!(emu.registers()[0], 0x05);
+    debugger.step(&mut emu, &mut rng, &keys).unwrap();
+    assert_eq!(emu.registers()[1], 0x06);
+  }
+}
@@ -0,0 +1,21 @@
+//! error.rs
+//! Errors parsing the debugger breakpoints.
+
+use std::{error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BreakpointError {
+  InvalidAddress(String),
+  InvalidPattern(String),
+}
+
+impl fmt::Display for BreakpointError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::InvalidAddress(s) => write!(f, "Invalid breakpoint address: {}", s),
+      | Self::InvalidPattern(s) => write!(f, "Invalid breakpoint opcode pattern: {}", s),
+    }
+  }
+}
+
+impl Error for BreakpointError {}
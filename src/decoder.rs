@@ -3,20 +3,34 @@
 
 use crate::decoder::{error::DecodeError, opcodes::*};
 
+pub use self::encode::encode;
+
+mod encode;
 pub mod error;
+mod mnemonic;
 mod opcodes;
 
 /// Decodified instructions.
-/// See opcodes.rs for the meaning of each variant.
+/// See opcodes.rs for the meaning of each variant, and mnemonic.rs for its assembly syntax.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
+  Sys(usize),                // 0x0nnn
   Cls,                       // 0x00E0
   Return,                    // 0x00EE
+  ScrollDown(u8),            // 0x00Cn
+  ScrollUp(u8),              // 0x00Dn
+  ScrollRight,               // 0x00FB
+  ScrollLeft,                // 0x00FC
+  Exit,                      // 0x00FD
+  Lores,                     // 0x00FE
+  Hires,                     // 0x00FF
   SetPC(usize),              // 0x1nnn
   Call(usize),               // 0x2nnn
   SeInmm(usize, u8),         // 0x3xnn
   SneInmm(usize, u8),        // 0x4xnn
   SeReg(usize, usize),       // 0x5xy0
+  StRange(usize, usize),     // 0x5xy2
+  LdRange(usize, usize),     // 0x5xy3
   SneReg(usize, usize),      // 0x9xy0
   LoadInmm(usize, u8),       // 0x6xnn
   Sum(usize, u8),            // 0x7xnn
@@ -40,10 +54,17 @@ pub enum Instruction {
   LoadDelay(usize),          // 0xFx15
   LoadSound(usize),          // 0xFx18
   AddI(usize),               // 0xFx1E
+  LoadLongI,                 // 0xF000 nnnn
+  Plane(u8),                 // 0xFn01
+  LoadAudio,                 // 0xF002
   LoadFont(usize),           // 0xFx29
+  LoadBigFont(usize),        // 0xFx30
+  Pitch(usize),              // 0xFx3A
   Bcd(usize),                // 0xFx33
   StMem(usize),              // 0xFx55
   LdMem(usize),              // 0xFx65
+  StFlags(usize),            // 0xFx75
+  LdFlags(usize),            // 0xFx85
 }
 
 /// Convert a binary instruction into an enum variant.\
@@ -56,7 +77,17 @@ pub enum Instruction {
 /// - Nine instructions that share 0xF.
 ///
 /// This give a total of 34 instructions for the original CHIP-8 from 1970.
-/// There is an additional instructions (0x0NNN), but it is not used in most roms.
+/// There is an additional instruction (0x0NNN), calling machine code in the COSMAC VIP. Any
+/// other word of the 0x0 group decodes as Instruction::Sys, run by the native routines of the
+/// emulator.
+///
+/// SUPER-CHIP 1.1 adds six more in the 0x0 group (scroll, exit and resolution switch),
+/// and three in the 0xF group (big font and RPL flags). Dxy0 keeps decoding as Display,
+/// the 16x16 sprite is a matter of the emulator.
+///
+/// XO-CHIP adds scroll up in the 0x0 group, two register range instructions in the 0x5 group,
+/// and four in the 0xF group. 0xF000 is the only instruction 4 bytes long, its second word
+/// (the address) is read by the emulator when executing Instruction::LoadLongI.
 ///
 /// The decoder use one main match block, comparing the first nibble. If match with one shared,
 /// it enters in a second match, comparing the last or last two nibbles.
@@ -67,7 +98,14 @@ pub fn decode(instr: u16) -> Result<Instruction, DecodeError> {
     | 0x0000 => match instr {
       | CLS => Ok(Instruction::Cls),
       | RET => Ok(Instruction::Return),
-      | _ => Err(DecodeError::Unknown(instr)),
+      | SCR_RIGHT => Ok(Instruction::ScrollRight),
+      | SCR_LEFT => Ok(Instruction::ScrollLeft),
+      | EXIT => Ok(Instruction::Exit),
+      | LORES => Ok(Instruction::Lores),
+      | HIRES => Ok(Instruction::Hires),
+      | _ if instr & 0xFFF0 == SCR_DOWN => Ok(Instruction::ScrollDown((instr & 0x000F) as u8)),
+      | _ if instr & 0xFFF0 == SCR_UP => Ok(Instruction::ScrollUp((instr & 0x000F) as u8)),
+      | _ => Ok(Instruction::Sys((instr & 0x0FFF) as usize)),
     },
     | SET_PC => {
       let inmm = (instr & 0x0FFF) as usize;
@@ -87,10 +125,16 @@ pub fn decode(instr: u16) -> Result<Instruction, DecodeError> {
       let inmm = (instr & 0x00FF) as u8;
       Ok(Instruction::SneInmm(reg, inmm))
     },
-    | SE_REG => {
+    | 0x5000 => {
       let reg_x = ((instr & 0x0F00) >> 8) as usize;
       let reg_y = ((instr & 0x00F0) >> 4) as usize;
-      Ok(Instruction::SeReg(reg_x, reg_y))
+      let opcode = instr & 0xF00F;
+      match opcode {
+        | SE_REG => Ok(Instruction::SeReg(reg_x, reg_y)),
+        | ST_RANGE => Ok(Instruction::StRange(reg_x, reg_y)),
+        | LD_RANGE => Ok(Instruction::LdRange(reg_x, reg_y)),
+        | _ => Err(DecodeError::Unknown(instr)),
+      }
     },
     | LD_INMM => {
       let reg = ((instr & 0x0F00) >> 8) as usize;
@@ -156,15 +200,22 @@ pub fn decode(instr: u16) -> Result<Instruction, DecodeError> {
       let reg = ((instr & 0x0F00) >> 8) as usize;
       let opcode = instr & 0xF0FF;
       match opcode {
+        | LD_LONG_I if reg == 0 => Ok(Instruction::LoadLongI),
+        | PLANE => Ok(Instruction::Plane(reg as u8)),
+        | LD_AUDIO if reg == 0 => Ok(Instruction::LoadAudio),
         | ST_DELAY => Ok(Instruction::GetDelay(reg)),
         | WAIT_KEY => Ok(Instruction::WaitKey(reg)),
         | LD_DELAY => Ok(Instruction::LoadDelay(reg)),
         | LD_SOUND => Ok(Instruction::LoadSound(reg)),
         | ADD_I => Ok(Instruction::AddI(reg)),
         | LD_FONT => Ok(Instruction::LoadFont(reg)),
+        | LD_BIG_FONT => Ok(Instruction::LoadBigFont(reg)),
+        | PITCH => Ok(Instruction::Pitch(reg)),
         | BCD => Ok(Instruction::Bcd(reg)),
         | ST_MEM => Ok(Instruction::StMem(reg)),
         | LD_MEM => Ok(Instruction::LdMem(reg)),
+        | ST_FLAGS => Ok(Instruction::StFlags(reg)),
+        | LD_FLAGS => Ok(Instruction::LdFlags(reg)),
         | _ => Err(DecodeError::Unknown(instr)),
       }
     },
@@ -177,6 +228,13 @@ pub fn decode(instr: u16) -> Result<Instruction, DecodeError> {
 mod test {
   use crate::decoder::{Instruction, decode};
 
+  #[test]
+  fn test_sys() {
+    assert_eq!(decode(0x0000), Ok(Instruction::Sys(0x000)));
+    assert_eq!(decode(0x0123), Ok(Instruction::Sys(0x123)));
+    assert_eq!(decode(0x00E1), Ok(Instruction::Sys(0x0E1)));
+  }
+
   #[test]
   fn test_cls() {
     assert_eq!(decode(0x00E0), Ok(Instruction::Cls));
@@ -270,6 +328,8 @@ mod test {
   #[test]
   fn test_snereg() {
     assert_eq!(decode(0x9FF0), Ok(Instruction::SneReg(0xF, 0xF)));
+    // The last nibble is ignored, as in the original interpreter.
+    assert_eq!(decode(0x9FF1), Ok(Instruction::SneReg(0xF, 0xF)));
   }
 
   #[test]
@@ -301,4 +361,64 @@ mod test {
   fn test_nskp() {
     assert_eq!(decode(0xEFA1), Ok(Instruction::Snkip(0xF)));
   }
+
+  #[test]
+  fn test_scroll() {
+    assert_eq!(decode(0x00C5), Ok(Instruction::ScrollDown(0x5)));
+    assert_eq!(decode(0x00FB), Ok(Instruction::ScrollRight));
+    assert_eq!(decode(0x00FC), Ok(Instruction::ScrollLeft));
+  }
+
+  #[test]
+  fn test_resolution() {
+    assert_eq!(decode(0x00FE), Ok(Instruction::Lores));
+    assert_eq!(decode(0x00FF), Ok(Instruction::Hires));
+  }
+
+  #[test]
+  fn test_exit() {
+    assert_eq!(decode(0x00FD), Ok(Instruction::Exit));
+  }
+
+  #[test]
+  fn test_ldbigfont() {
+    assert_eq!(decode(0xFF30), Ok(Instruction::LoadBigFont(0xF)));
+  }
+
+  #[test]
+  fn test_flags() {
+    assert_eq!(decode(0xF775), Ok(Instruction::StFlags(0x7)));
+    assert_eq!(decode(0xF785), Ok(Instruction::LdFlags(0x7)));
+  }
+
+  #[test]
+  fn test_range() {
+    assert_eq!(decode(0x5122), Ok(Instruction::StRange(0x1, 0x2)));
+    assert_eq!(decode(0x5123), Ok(Instruction::LdRange(0x1, 0x2)));
+    assert!(decode(0x5121).is_err());
+  }
+
+  #[test]
+  fn test_xochip_group_f() {
+    assert_eq!(decode(0xF000), Ok(Instruction::LoadLongI));
+    assert_eq!(decode(0xF201), Ok(Instruction::Plane(0x2)));
+    assert_eq!(decode(0xF002), Ok(Instruction::LoadAudio));
+    assert_eq!(decode(0xF53A), Ok(Instruction::Pitch(0x5)));
+    assert!(decode(0xF100).is_err());
+    assert!(decode(0xF102).is_err());
+  }
+
+  #[test]
+  fn test_scroll_up() {
+    assert_eq!(decode(0x00D3), Ok(Instruction::ScrollUp(0x3)));
+  }
+
+  #[test]
+  fn test_mnemonic() {
+    assert_eq!(decode(0x00E0).unwrap().to_string(), "CLS");
+    assert_eq!(decode(0x6120).unwrap().to_string(), "LD V1, 0x20");
+    assert_eq!(decode(0xD015).unwrap().to_string(), "DRW V0, V1, 5");
+    assert_eq!(decode(0xA2F0).unwrap().to_string(), "LD I, 0x2F0");
+    assert_eq!(decode(0xFA65).unwrap().to_string(), "LD VA, [I]");
+  }
 }
@@ -0,0 +1,119 @@
+//! encode.rs
+//! Inverse of the decoder, converting an enum variant into its binary instruction.
+
+use crate::decoder::{Instruction, opcodes::*};
+
+/// Helpers to place the operands in their nibbles.
+fn x(reg: usize) -> u16 {
+  debug_assert!(reg < 0x10);
+  (reg as u16) << 8
+}
+
+fn y(reg: usize) -> u16 {
+  debug_assert!(reg < 0x10);
+  (reg as u16) << 4
+}
+
+fn nnn(addr: usize) -> u16 {
+  debug_assert!(addr <= 0xFFF);
+  addr as u16
+}
+
+/// Convert an enum variant into a binary instruction, so decode(encode(i)) == i.
+///
+/// Operands out of range (registers above 0xF, addresses above 0xFFF) are not valid
+/// instructions, only checked in debug builds.
+/// Instruction::LoadLongI only encodes the first word (0xF000), the address goes in the next one.
+/// Instruction::Sys with the address of another 0x0 instruction (like 0x0E0) encodes as that one.
+pub fn encode(instr: Instruction) -> u16 {
+  match instr {
+    | Instruction::Sys(a) => SYS | nnn(a),
+    | Instruction::Cls => CLS,
+    | Instruction::Return => RET,
+    | Instruction::ScrollDown(n) => SCR_DOWN | (n as u16 & 0xF),
+    | Instruction::ScrollUp(n) => SCR_UP | (n as u16 & 0xF),
+    | Instruction::ScrollRight => SCR_RIGHT,
+    | Instruction::ScrollLeft => SCR_LEFT,
+    | Instruction::Exit => EXIT,
+    | Instruction::Lores => LORES,
+    | Instruction::Hires => HIRES,
+    | Instruction::SetPC(n) => SET_PC | nnn(n),
+    | Instruction::Call(n) => CALL | nnn(n),
+    | Instruction::SeInmm(r, n) => SE_INMM | x(r) | n as u16,
+    | Instruction::SneInmm(r, n) => SNE_INMM | x(r) | n as u16,
+    | Instruction::SeReg(rx, ry) => SE_REG | x(rx) | y(ry),
+    | Instruction::StRange(rx, ry) => ST_RANGE | x(rx) | y(ry),
+    | Instruction::LdRange(rx, ry) => LD_RANGE | x(rx) | y(ry),
+    | Instruction::SneReg(rx, ry) => SNE_REG | x(rx) | y(ry),
+    | Instruction::LoadInmm(r, n) => LD_INMM | x(r) | n as u16,
+    | Instruction::Sum(r, n) => SUM | x(r) | n as u16,
+    | Instruction::LoadI(n) => LD_I | nnn(n),
+    | Instruction::Jump(n) => JUMP | nnn(n),
+    | Instruction::Rand(r, n) => RAND | x(r) | n as u16,
+    | Instruction::Display(rx, ry, n) => DISPLAY | x(rx) | y(ry) | (n as u16 & 0xF),
+    | Instruction::LoadReg(rx, ry) => LD_REG | x(rx) | y(ry),
+    | Instruction::Or(rx, ry) => OR | x(rx) | y(ry),
+    | Instruction::And(rx, ry) => AND | x(rx) | y(ry),
+    | Instruction::Xor(rx, ry) => XOR | x(rx) | y(ry),
+    | Instruction::Add(rx, ry) => ADD | x(rx) | y(ry),
+    | Instruction::Sub(rx, ry) => SUB | x(rx) | y(ry),
+    | Instruction::ShiftRight(rx, ry) => SHR | x(rx) | y(ry),
+    | Instruction::SubRev(rx, ry) => SUBN | x(rx) | y(ry),
+    | Instruction::ShiftLeft(rx, ry) => SHL | x(rx) | y(ry),
+    | Instruction::Skip(r) => SKP | x(r),
+    | Instruction::Snkip(r) => SNKP | x(r),
+    | Instruction::GetDelay(r) => ST_DELAY | x(r),
+    | Instruction::WaitKey(r) => WAIT_KEY | x(r),
+    | Instruction::LoadDelay(r) => LD_DELAY | x(r),
+    | Instruction::LoadSound(r) => LD_SOUND | x(r),
+    | Instruction::AddI(r) => ADD_I | x(r),
+    | Instruction::LoadLongI => LD_LONG_I,
+    | Instruction::Plane(n) => PLANE | x(n as usize),
+    | Instruction::LoadAudio => LD_AUDIO,
+    | Instruction::LoadFont(r) => LD_FONT | x(r),
+    | Instruction::LoadBigFont(r) => LD_BIG_FONT | x(r),
+    | Instruction::Pitch(r) => PITCH | x(r),
+    | Instruction::Bcd(r) => BCD | x(r),
+    | Instruction::StMem(r) => ST_MEM | x(r),
+    | Instruction::LdMem(r) => LD_MEM | x(r),
+    | Instruction::StFlags(r) => ST_FLAGS | x(r),
+    | Instruction::LdFlags(r) => LD_FLAGS | x(r),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashSet;
+  use std::mem::discriminant;
+
+  use crate::decoder::{Instruction, decode, encode};
+
+  /// Every decodable word must encode back to itself, so decode(encode(i)) == i.
+  /// Walking the whole 16 bit space covers every variant with every operand.
+  ///
+  /// The exception is 9xyN, decoded as 9xy0 whatever the N: only 9xy0 is canonical.
+  #[test]
+  fn test_roundtrip() {
+    let mut variants = HashSet::new();
+    for word in 0..=u16::MAX {
+      if let Ok(instr) = decode(word) {
+        if word & 0xF000 == 0x9000 && word & 0x000F != 0 {
+          assert_eq!(encode(instr), word & 0xFFF0, "{:?}", instr);
+          continue;
+        }
+        assert_eq!(encode(instr), word, "{:?}", instr);
+        assert_eq!(decode(encode(instr)), Ok(instr));
+        variants.insert(discriminant(&instr));
+      }
+    }
+    // Keep in sync with the number of variants in Instruction.
+    assert_eq!(variants.len(), 51);
+  }
+
+  #[test]
+  fn test_encode() {
+    assert_eq!(encode(Instruction::Display(0x0, 0x1, 5)), 0xD015);
+    assert_eq!(encode(Instruction::LoadI(0x2F0)), 0xA2F0);
+    assert_eq!(encode(Instruction::ShiftLeft(0x3, 0x4)), 0x834E);
+  }
+}
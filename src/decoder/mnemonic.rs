@@ -0,0 +1,64 @@
+//! mnemonic.rs
+//! Conventional assembly syntax for each instruction (Cowgod's, extended for SCHIP and XO-CHIP).
+
+use std::fmt;
+
+use crate::decoder::Instruction;
+
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      | Self::Sys(n) => write!(f, "SYS 0x{:03X}", n),
+      | Self::Cls => write!(f, "CLS"),
+      | Self::Return => write!(f, "RET"),
+      | Self::ScrollDown(n) => write!(f, "SCD {}", n),
+      | Self::ScrollUp(n) => write!(f, "SCU {}", n),
+      | Self::ScrollRight => write!(f, "SCR"),
+      | Self::ScrollLeft => write!(f, "SCL"),
+      | Self::Exit => write!(f, "EXIT"),
+      | Self::Lores => write!(f, "LOW"),
+      | Self::Hires => write!(f, "HIGH"),
+      | Self::SetPC(n) => write!(f, "JP 0x{:03X}", n),
+      | Self::Call(n) => write!(f, "CALL 0x{:03X}", n),
+      | Self::SeInmm(x, n) => write!(f, "SE V{:X}, 0x{:02X}", x, n),
+      | Self::SneInmm(x, n) => write!(f, "SNE V{:X}, 0x{:02X}", x, n),
+      | Self::SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+      | Self::StRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+      | Self::LdRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+      | Self::SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+      | Self::LoadInmm(x, n) => write!(f, "LD V{:X}, 0x{:02X}", x, n),
+      | Self::Sum(x, n) => write!(f, "ADD V{:X}, 0x{:02X}", x, n),
+      | Self::LoadI(n) => write!(f, "LD I, 0x{:03X}", n),
+      | Self::Jump(n) => write!(f, "JP V0, 0x{:03X}", n),
+      | Self::Rand(x, n) => write!(f, "RND V{:X}, 0x{:02X}", x, n),
+      | Self::Display(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+      | Self::LoadReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+      | Self::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+      | Self::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+      | Self::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+      | Self::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+      | Self::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+      | Self::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+      | Self::SubRev(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+      | Self::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+      | Self::Skip(x) => write!(f, "SKP V{:X}", x),
+      | Self::Snkip(x) => write!(f, "SKNP V{:X}", x),
+      | Self::GetDelay(x) => write!(f, "LD V{:X}, DT", x),
+      | Self::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+      | Self::LoadDelay(x) => write!(f, "LD DT, V{:X}", x),
+      | Self::LoadSound(x) => write!(f, "LD ST, V{:X}", x),
+      | Self::AddI(x) => write!(f, "ADD I, V{:X}", x),
+      | Self::LoadLongI => write!(f, "LD I, LONG"),
+      | Self::Plane(n) => write!(f, "PLANE {}", n),
+      | Self::LoadAudio => write!(f, "AUDIO"),
+      | Self::LoadFont(x) => write!(f, "LD F, V{:X}", x),
+      | Self::LoadBigFont(x) => write!(f, "LD HF, V{:X}", x),
+      | Self::Pitch(x) => write!(f, "PITCH V{:X}", x),
+      | Self::Bcd(x) => write!(f, "LD B, V{:X}", x),
+      | Self::StMem(x) => write!(f, "LD [I], V{:X}", x),
+      | Self::LdMem(x) => write!(f, "LD V{:X}, [I]", x),
+      | Self::StFlags(x) => write!(f, "LD R, V{:X}", x),
+      | Self::LdFlags(x) => write!(f, "LD V{:X}, R", x),
+    }
+  }
+}
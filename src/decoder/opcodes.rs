@@ -12,6 +12,10 @@ pub const SE_INMM: u16 = 0x3000;
 pub const SNE_INMM: u16 = 0x4000;
 /// 0x5xy0: Skip next instruction if VX == VY.
 pub const SE_REG: u16 = 0x5000;
+/// 0x5xy2: Store registers VX through VY starting at location I (XO-CHIP).
+pub const ST_RANGE: u16 = 0x5002;
+/// 0x5xy3: Load registers VX through VY starting at location I (XO-CHIP).
+pub const LD_RANGE: u16 = 0x5003;
 /// 0x6xnn: Put the value nn into reg VX.
 pub const LD_INMM: u16 = 0x6000;
 /// 0x7xnn: Add the value nn to the value in VX ,storing in VX (no carry).
@@ -30,8 +34,24 @@ pub const DISPLAY: u16 = 0xD000;
 // Instructions with first nibble equal (GROUP 0).
 /// 0x00E0: Clear the display.
 pub const CLS: u16 = 0x00E0;
+/// 0x0nnn: Call the machine code routine at nnn (any other 0x0 word).
+pub const SYS: u16 = 0x0000;
 /// 0x00EE: Return from a subroutine.
 pub const RET: u16 = 0x00EE;
+/// 0x00Cn: Scroll the display n pixels down (SUPER-CHIP).
+pub const SCR_DOWN: u16 = 0x00C0;
+/// 0x00Dn: Scroll the display n pixels up (XO-CHIP).
+pub const SCR_UP: u16 = 0x00D0;
+/// 0x00FB: Scroll the display 4 pixels to the right (SUPER-CHIP).
+pub const SCR_RIGHT: u16 = 0x00FB;
+/// 0x00FC: Scroll the display 4 pixels to the left (SUPER-CHIP).
+pub const SCR_LEFT: u16 = 0x00FC;
+/// 0x00FD: Exit the interpreter (SUPER-CHIP).
+pub const EXIT: u16 = 0x00FD;
+/// 0x00FE: Switch to the 64x32 low resolution mode (SUPER-CHIP).
+pub const LORES: u16 = 0x00FE;
+/// 0x00FF: Switch to the 128x64 high resolution mode (SUPER-CHIP).
+pub const HIRES: u16 = 0x00FF;
 
 // Instructions with first nibble equal (GROUP 8).
 /// 0x8xy0: Store the value in VY in reg VX.
@@ -60,6 +80,12 @@ pub const SKP: u16 = 0xE09E;
 pub const SNKP: u16 = 0xE0A1;
 
 // Instructions with first nibble equal (GROUP F).
+/// 0xF000 nnnn: Set I to the 16 bit address in the next word (XO-CHIP).
+pub const LD_LONG_I: u16 = 0xF000;
+/// 0xFn01: Select the bit planes n for drawing, clearing and scrolling (XO-CHIP).
+pub const PLANE: u16 = 0xF001;
+/// 0xF002: Load the 16 bytes audio pattern starting at location I (XO-CHIP).
+pub const LD_AUDIO: u16 = 0xF002;
 /// 0xFx07: Load the delay reg in reg VX.
 pub const ST_DELAY: u16 = 0xF007;
 /// 0xFx0A: Enter in a infinite loop until a key is pressed, storing it in VX.
@@ -72,9 +98,17 @@ pub const LD_SOUND: u16 = 0xF018;
 pub const ADD_I: u16 = 0xF01E;
 /// 0xFx29: Set I to the location of the sprite in VX.
 pub const LD_FONT: u16 = 0xF029;
+/// 0xFx30: Set I to the location of the big sprite in VX (SUPER-CHIP).
+pub const LD_BIG_FONT: u16 = 0xF030;
+/// 0xFx3A: Set the audio pattern playback rate from VX (XO-CHIP).
+pub const PITCH: u16 = 0xF03A;
 /// 0xFx33: Store in I, I+1, I+2 the digits in digital of VX.
 pub const BCD: u16 = 0xF033;
 /// 0xFx55: Store registers V0 through VX starting at location I.
 pub const ST_MEM: u16 = 0xF055;
 /// 0xFx65: store the values in memory starting in I storing from V0 to VX.
 pub const LD_MEM: u16 = 0xF065;
+/// 0xFx75: Store registers V0 through VX in the RPL user flags (SUPER-CHIP).
+pub const ST_FLAGS: u16 = 0xF075;
+/// 0xFx85: Load registers V0 through VX from the RPL user flags (SUPER-CHIP).
+pub const LD_FLAGS: u16 = 0xF085;
@@ -0,0 +1,85 @@
+//! disasm.rs
+//! Disassembler, producing an address annotated listing of a program.
+
+use std::fmt;
+
+use crate::decoder::{Instruction, decode};
+
+/// A single line of the listing: an instruction, or data when the word can't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+  /// Address of the first byte.
+  pub addr: usize,
+  /// Raw bytes, 2 for most instructions, 4 for XO-CHIP 0xF000 and 1 for a trailing odd byte.
+  pub bytes: Vec<u8>,
+  /// Mnemonic of the instruction, or DB/DW directive for data.
+  pub text: String,
+}
+
+impl fmt::Display for Line {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let hex: Vec<String> =
+      self.bytes.chunks(2).map(|c| c.iter().map(|b| format!("{:02X}", b)).collect()).collect();
+    write!(f, "0x{:04X}  {:<10}{}", self.addr, hex.join(" "), self.text)
+  }
+}
+
+/// Walk the program (loaded in origin) two bytes at a time, decoding each word.
+///
+/// This is a linear sweep, so data mixed with the code is decoded as instructions when possible.
+/// Words that are not an instruction are emitted as DW, and a trailing odd byte as DB.
+pub fn disassemble(program: &[u8], origin: usize) -> Vec<Line> {
+  let mut lines = Vec::new();
+  let mut pos = 0;
+  while pos < program.len() {
+    let addr = origin + pos;
+    if pos + 1 >= program.len() {
+      lines.push(Line {
+        addr,
+        bytes: vec![program[pos]],
+        text: format!("DB 0x{:02X}", program[pos]),
+      });
+      break;
+    }
+    let word = ((program[pos] as u16) << 8) | program[pos + 1] as u16;
+    let line = match decode(word) {
+      // The long I load takes the next word as address.
+      | Ok(Instruction::LoadLongI) if pos + 3 < program.len() => {
+        let long = ((program[pos + 2] as u16) << 8) | program[pos + 3] as u16;
+        let text = format!("{} 0x{:04X}", Instruction::LoadLongI, long);
+        Line { addr, bytes: program[pos..(pos + 4)].to_vec(), text }
+      },
+      | Ok(instr) => {
+        Line { addr, bytes: program[pos..(pos + 2)].to_vec(), text: instr.to_string() }
+      },
+      | Err(_) => {
+        Line { addr, bytes: program[pos..(pos + 2)].to_vec(), text: format!("DW 0x{:04X}", word) }
+      },
+    };
+    pos += line.bytes.len();
+    lines.push(line);
+  }
+  lines
+}
+
+#[cfg(test)]
+mod test {
+  use crate::disasm::disassemble;
+
+  #[test]
+  fn test_disassemble() {
+    let program = [0x00, 0xE0, 0x61, 0x20, 0xFF, 0xFF, 0xF0, 0x00, 0x12, 0x34, 0xD0, 0x15, 0xAB];
+    let text: Vec<String> = disassemble(&program, 0x200).iter().map(|l| l.text.clone()).collect();
+    assert_eq!(
+      text,
+      ["CLS", "LD V1, 0x20", "DW 0xFFFF", "LD I, LONG 0x1234", "DRW V0, V1, 5", "DB 0xAB"]
+    );
+  }
+
+  #[test]
+  fn test_line_format() {
+    let lines = disassemble(&[0xA2, 0xF0, 0xF0, 0x00, 0x12, 0x34], 0x200);
+    assert_eq!(lines[0].to_string(), "0x0200  A2F0      LD I, 0x2F0");
+    assert_eq!(lines[1].to_string(), "0x0202  F000 1234 LD I, LONG 0x1234");
+  }
+}
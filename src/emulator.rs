@@ -1,41 +1,65 @@
 //! emulator.rs
 //! Hardware emulator module, although CHIP-8 was never implemented.
 
+mod cache;
 mod display;
 pub mod error;
 mod font;
+pub mod quirks;
+pub mod routines;
+pub mod savestate;
 mod stack;
 
+pub use self::quirks::Quirks;
+pub use self::routines::{Routine, Routines};
+pub use self::savestate::error::StateError;
+pub use self::stack::{Stack, error::StackError};
+
+use self::cache::DecodeCache;
 use self::display::*;
 use self::error::EmuError;
 use self::font::*;
-use self::stack::{Stack, error::StackError};
-use crate::decoder::Instruction;
-use crate::cli::COMPATIBILITY;
+use crate::decoder::{Instruction, decode};
+use crate::fault::error::FaultKind;
+use crate::fault::{FaultClass, FaultPolicy};
 
-use anyhow::Result;
 use rand::prelude::*;
 
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
+/// SUPER-CHIP high resolution mode.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
 
-const MEMORY_SIZE: usize = 4096;
-const REG_SIZE: usize = 16;
+/// XO-CHIP extends the memory to 64KB, original programs only use the first 4KB.
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const REG_SIZE: usize = 16;
 /// Due the first 512 bytes are reserved, programs start in this address.
-const START_ADDR: usize = 0x200;
+pub const START_ADDR: usize = 0x200;
 /// Semi special reg, used by many instructions as flag.
 const REG_F: usize = 15;
 /// Number of keys in the pad.
 const KEY_SIZE: usize = 16;
+/// Number of RPL user flags (8 in SUPER-CHIP, extended to 16 by XO-CHIP).
+const FLAGS_SIZE: usize = 16;
+/// Size in bytes of the XO-CHIP audio pattern buffer (128 1-bit samples).
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+/// Default XO-CHIP pitch, equivalent to a playback rate of 4000Hz.
+const DEFAULT_PITCH: u8 = 64;
+/// Frequency (Hz) the timers decrease at. Frontends run one frame per tick.
+pub const TIMER_FREQUENCY: u32 = 60;
 
 /// The CHIP-8 count with the next specifications:
-/// - 4KB of memory. The first 512 bytes are reserved, therefore should not be used by the programs.
+/// - 4KB of memory (64KB in XO-CHIP). The first 512 bytes are reserved, therefore should not be used by the programs.
 /// - 16 general purpose 8 bit registers.
 /// - 1 special 16 bit register named I, used for storing memory addresses.
 /// - 2 special purpose 8 bit register, for delay and sound.
 /// - 16 bit program counter pointer.
-/// - 1 64x32 monochrome display. See src/emulator/display.rs
+/// - 1 64x32 monochrome display (128x64 in SUPER-CHIP high resolution, 2 bit planes in XO-CHIP).
+///   See src/emulator/display.rs
 /// - 1 16x8 stack. See src/emulator/stackrs
+/// - 16 RPL user flags, a SUPER-CHIP addition to persist registers.
+/// - 1 16 bytes audio pattern buffer and its pitch register, an XO-CHIP addition.
 ///
 /// For the registers i and pc, the struct will use an usize to reduce the number of casts.
 #[derive(Debug)]
@@ -48,30 +72,164 @@ pub struct Emulator {
   reg_sound: u8,
   display: Display,
   stack: Stack,
+  rpl: [u8; FLAGS_SIZE],
+  planes: u8,
+  /// None until the program loads a pattern.
+  audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+  pitch: u8,
   refresh: bool,
+  exited: bool,
+  quirks: Quirks,
+  policy: FaultPolicy,
+  routines: Routines,
+  cache: DecodeCache,
 }
 
 impl Emulator {
-  /// Creates a new instance of the emulator.
-  pub fn new() -> Self {
-    let mut this = Self::default();
+  /// Creates a new instance of the emulator, with the behaviour described by quirks.
+  pub fn new(quirks: Quirks) -> Self {
+    let mut this = Self { quirks, ..Self::default() };
     this.load_fonts();
     this
   }
 
+  /// Return the quirks the emulator is running with.
+  pub fn quirks(&self) -> Quirks {
+    self.quirks
+  }
+
+  /// Return the action for each kind of fault.
+  pub fn fault_policy(&self) -> FaultPolicy {
+    self.policy
+  }
+
+  /// Set the action for each kind of fault. Only Wrap changes the emulator: the other actions
+  /// are up to the caller, on the errors of execute.
+  pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+    self.policy = policy;
+  }
+
+  /// General purpose registers, V0 to VF.
+  pub fn registers(&self) -> &[u8; REG_SIZE] {
+    &self.reg
+  }
+
+  /// General purpose registers, to change them (from a native routine).
+  pub fn registers_mut(&mut self) -> &mut [u8; REG_SIZE] {
+    &mut self.reg
+  }
+
+  /// Current value of the register I.
+  pub fn reg_i(&self) -> usize {
+    self.reg_i
+  }
+
+  /// Current value of the program counter.
+  pub fn reg_pc(&self) -> usize {
+    self.reg_pc
+  }
+
+  /// Current value of the delay timer.
+  pub fn delay_timer(&self) -> u8 {
+    self.reg_delay
+  }
+
+  /// Current value of the sound timer.
+  pub fn sound_timer(&self) -> u8 {
+    self.reg_sound
+  }
+
+  /// The call stack.
+  pub fn stack(&self) -> &Stack {
+    &self.stack
+  }
+
+  /// The whole memory.
+  pub fn memory(&self) -> &[u8] {
+    &self.memory
+  }
+
+  /// Return the instruction stored in addr, without modifying reg_pc.
+  pub fn opcode_at(&self, addr: usize) -> Option<u16> {
+    let high = *self.memory.get(addr)?;
+    let low = *self.memory.get(addr + 1)?;
+    Some(((high as u16) << 8) | low as u16)
+  }
+
+  /// Set the register I, without checking the address.
+  pub fn set_reg_i(&mut self, value: usize) {
+    self.reg_i = value;
+  }
+
+  /// Set the program counter, without checking the address (fetch does).
+  pub fn set_reg_pc(&mut self, value: usize) {
+    self.reg_pc = value;
+  }
+
+  /// The memory, to change it. Drops the cache of decoded instructions, and with the new code
+  /// version every block of the engines too.
+  pub fn memory_mut(&mut self) -> &mut [u8] {
+    self.cache.clear();
+    &mut self.memory
+  }
+
+  /// Register a native routine, run when the program calls its address with 0nnn. Replaces the
+  /// routine already in the address, if any. See routines.rs.
+  pub fn register_routine<F>(&mut self, addr: usize, routine: F)
+  where
+    F: FnMut(&mut Emulator) -> Result<(), EmuError> + Send + 'static,
+  {
+    debug_assert!(addr <= 0xFFF);
+    self.routines.insert(addr, Box::new(routine));
+  }
+
+  /// Remove the native routine in the address, returning it.
+  pub fn unregister_routine(&mut self, addr: usize) -> Option<Routine> {
+    self.routines.remove(addr)
+  }
+
+  /// The native routines registered, by address.
+  pub fn routines(&self) -> &Routines {
+    &self.routines
+  }
+
   /// Return the instruction pointed by reg_pc, then increase reg_pc.
   /// Remember each instruction is 16 bit, in BE.
   pub fn fetch(&mut self) -> Result<u16, EmuError> {
-    if self.reg_pc + 1 >= MEMORY_SIZE {
+    if self.reg_pc + 1 >= MEMORY_SIZE && !self.policy.wraps(FaultClass::InvalidAddress) {
       return Err(EmuError::InvalidAddress(self.reg_pc));
     }
-    let value_high = (self.memory[self.reg_pc] as u16) << 8;
-    let value_low = self.memory[self.reg_pc + 1] as u16;
+    let value_high = (self.memory[self.reg_pc % MEMORY_SIZE] as u16) << 8;
+    let value_low = self.memory[(self.reg_pc + 1) % MEMORY_SIZE] as u16;
     let instr = value_high + value_low;
-    self.reg_pc += 2;
+    self.reg_pc = (self.reg_pc + 2) % MEMORY_SIZE;
     Ok(instr)
   }
 
+  /// Fetch the instruction pointed by reg_pc and decode it, like fetch and then decode.
+  ///
+  /// The decoded instructions are kept by address, so each one is only decoded again after the
+  /// program writes over it. See cache.rs.
+  pub fn fetch_decoded(&mut self) -> Result<Instruction, FaultKind> {
+    let pc = self.reg_pc;
+    if let Some(instr) = self.cache.get(pc) {
+      self.reg_pc = (pc + 2) % MEMORY_SIZE;
+      return Ok(instr);
+    }
+    let instr = decode(self.fetch()?)?;
+    // An instruction wrapping around the end of the memory is never cached.
+    if pc + 1 < MEMORY_SIZE {
+      self.cache.insert(pc, instr);
+    }
+    Ok(instr)
+  }
+
+  /// Changes every time the program writes over instructions already decoded by fetch_decoded,
+  /// or the memory is changed with memory_mut or load_state.
+  pub fn code_version(&self) -> u64 {
+    self.cache.version()
+  }
+
   /// Decrease each timer in 0 if they are above 0.
   pub fn decrease_timers(&mut self) {
     if self.reg_delay > 0 {
@@ -82,6 +240,11 @@ impl Emulator {
     }
   }
 
+  /// Return true while the sound timer is above 0, so the buzzer should be sounding.
+  pub fn is_sound_active(&self) -> bool {
+    self.reg_sound > 0
+  }
+
   /// Return true if the display have been altered in the last frame and should be refreshed
   pub fn should_refresh(&self) -> bool {
     self.refresh
@@ -93,25 +256,84 @@ impl Emulator {
   }
 
   /// Small wrapper around the internal display, required by the frontend.
-  pub fn display_val(&self, x: usize, y: usize) -> bool {
-    self.display.get(x, y)
+  /// Return the color index of the pixel (0 to 3), one bit per plane.
+  pub fn display_val(&self, x: usize, y: usize) -> u8 {
+    self.display.color(x, y)
+  }
+
+  /// Current width of the display, 64 or 128 pixels.
+  pub fn display_width(&self) -> usize {
+    self.display.width()
+  }
+
+  /// Current height of the display, 32 or 64 pixels.
+  pub fn display_height(&self) -> usize {
+    self.display.height()
+  }
+
+  /// Return true if the program executed the SUPER-CHIP exit instruction (0x00FD).
+  pub fn has_exited(&self) -> bool {
+    self.exited
+  }
+
+  /// XO-CHIP audio pattern, 128 1-bit samples played while the sound timer is active.
+  /// None if the program has not loaded any, and the buzzer sounds instead.
+  pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+    self.audio_pattern.as_ref()
+  }
+
+  /// XO-CHIP pitch register. The pattern playback rate is 4000 * 2^((pitch - 64) / 48) Hz.
+  pub fn pitch(&self) -> u8 {
+    self.pitch
   }
 
   /// execute the corresponding instruction depending instr.
   /// Basically match each function with each Instruction.
-  pub fn execute(&mut self, instr: Instruction, rng: &mut ThreadRng, keys: &[bool]) -> Result<()> {
+  /// Any random generator can be used for the Rand instruction: a SeededRng makes the runs
+  /// reproducible.
+  pub fn execute<R: Rng + ?Sized>(
+    &mut self, instr: Instruction, rng: &mut R, keys: &[bool],
+  ) -> Result<(), EmuError> {
     match instr {
+      | Instruction::Sys(addr) => self.sys(addr)?,
       | Instruction::Cls => {
         self.refresh = true;
         self.clear_display();
       },
       | Instruction::Return => self.ret()?,
+      | Instruction::ScrollDown(n) => {
+        self.refresh = true;
+        self.display.scroll_down(self.planes, n as usize);
+      },
+      | Instruction::ScrollUp(n) => {
+        self.refresh = true;
+        self.display.scroll_up(self.planes, n as usize);
+      },
+      | Instruction::ScrollRight => {
+        self.refresh = true;
+        self.display.scroll_right(self.planes, 4);
+      },
+      | Instruction::ScrollLeft => {
+        self.refresh = true;
+        self.display.scroll_left(self.planes, 4);
+      },
+      | Instruction::Exit => self.exit(),
+      | Instruction::Lores => {
+        self.refresh = true;
+        self.display.set_hires(false);
+      },
+      | Instruction::Hires => {
+        self.refresh = true;
+        self.display.set_hires(true);
+      },
       | Instruction::SetPC(n) => self.set_pc(n),
       | Instruction::Call(n) => self.call(n)?,
       | Instruction::SeInmm(x, n) => self.se_inmm(x, n),
       | Instruction::SneInmm(x, n) => self.sne_inmm(x, n),
       | Instruction::SeReg(x, y) => self.se_reg(x, y),
       | Instruction::SneReg(x, y) => self.sne_reg(x, y),
+      | Instruction::StRange(x, y) => self.store_range(x, y)?,
+      | Instruction::LdRange(x, y) => self.load_range(x, y)?,
       | Instruction::LoadInmm(x, n) => self.load_inmm(x, n),
       | Instruction::Sum(x, n) => self.sum(x, n),
       | Instruction::LoadI(n) => self.load_i(n),
@@ -119,7 +341,7 @@ impl Emulator {
       | Instruction::Rand(x, n) => self.rand(x, n, rng),
       | Instruction::Display(x, y, n) => {
         self.refresh = true;
-        self.display(x, y, n);
+        self.display(x, y, n)?;
       },
       | Instruction::LoadReg(x, y) => self.load_reg(x, y),
       | Instruction::Or(x, y) => self.or(x, y),
@@ -137,10 +359,17 @@ impl Emulator {
       | Instruction::LoadDelay(x) => self.load_delay(x),
       | Instruction::LoadSound(x) => self.load_sound(x),
       | Instruction::AddI(x) => self.add_to_index(x),
+      | Instruction::LoadLongI => self.load_long_i()?,
+      | Instruction::Plane(n) => self.plane(n),
+      | Instruction::LoadAudio => self.load_audio()?,
       | Instruction::LoadFont(x) => self.load_font(x)?,
+      | Instruction::LoadBigFont(x) => self.load_big_font(x)?,
+      | Instruction::Pitch(x) => self.load_pitch(x),
       | Instruction::Bcd(x) => self.binary_dec(x)?,
       | Instruction::StMem(x) => self.store_mem(x)?,
       | Instruction::LdMem(x) => self.load_mem(x)?,
+      | Instruction::StFlags(x) => self.store_flags(x),
+      | Instruction::LdFlags(x) => self.load_flags(x),
     }
     Ok(())
   }
@@ -153,6 +382,7 @@ impl Emulator {
     for n in program.iter().enumerate() {
       self.memory[START_ADDR + n.0] = *n.1;
     }
+    self.cache.invalidate(START_ADDR, program.len());
     Ok(())
   }
 
@@ -164,14 +394,20 @@ impl Emulator {
         self.memory[rpos + byte.0] = *byte.1;
       }
     }
+    for font in BIG_FONTS.iter().enumerate() {
+      let rpos = BIG_FONT_START_ADDRESS + (font.0 * BIG_FONT_SIZE);
+      for byte in font.1.iter().enumerate() {
+        self.memory[rpos + byte.0] = *byte.1;
+      }
+    }
   }
 
   /// Print (standard output) the current state of the display. Used only for debugging.
   #[allow(dead_code)]
   pub fn dumb_print(&self) {
-    for y in 0..DISPLAY_HEIGHT {
-      for x in 0..DISPLAY_WIDTH {
-        if self.display.get(x, y) {
+    for y in 0..self.display.height() {
+      for x in 0..self.display.width() {
+        if self.display.color(x, y) != 0 {
           print!("█");
         } else {
           print!(" ");
@@ -193,20 +429,79 @@ impl Default for Emulator {
       reg_sound: 0,
       display: Display::new(),
       stack: Stack::new(),
+      rpl: [0; FLAGS_SIZE],
+      planes: 0b01,
+      audio_pattern: None,
+      pitch: DEFAULT_PITCH,
       refresh: false,
+      exited: false,
+      quirks: Quirks::default(),
+      policy: FaultPolicy::default(),
+      routines: Routines::default(),
+      cache: DecodeCache::default(),
     }
   }
 }
 
 impl Emulator {
-  /// Clear the display, setting all the pixels to off.
+  /// Return pos if it is inside the memory. Otherwise, an error, or pos wrapped around the memory
+  /// if the policy wraps invalid addresses.
+  fn address(&self, pos: usize) -> Result<usize, EmuError> {
+    if pos < MEMORY_SIZE {
+      Ok(pos)
+    } else if self.policy.wraps(FaultClass::InvalidAddress) {
+      Ok(pos % MEMORY_SIZE)
+    } else {
+      Err(EmuError::InvalidAddress(pos))
+    }
+  }
+
+  /// Return the key index if it is a valid one. Otherwise, an error, or the lowest nibble if the
+  /// policy wraps unknown keys.
+  fn key(&self, value: usize) -> Result<usize, EmuError> {
+    if value < KEY_SIZE {
+      Ok(value)
+    } else if self.policy.wraps(FaultClass::UnknownKey) {
+      Ok(value % KEY_SIZE)
+    } else {
+      Err(EmuError::UnknownKey(value))
+    }
+  }
+
+  /// Clear the display, setting all the pixels in the selected planes to off.
   fn clear_display(&mut self) {
-    self.display.clear();
+    self.display.clear(self.planes);
+  }
+
+  /// Skip the next instruction, increasing pc in 2 (wrapping around the memory, as fetch).
+  /// XO-CHIP 0xF000 is 4 bytes long, so it increases pc in 4 when it is the next one.
+  fn skip_next(&mut self) {
+    let next_high = self.memory[self.reg_pc % MEMORY_SIZE];
+    let next_low = self.memory[(self.reg_pc + 1) % MEMORY_SIZE];
+    let len = if next_high == 0xF0 && next_low == 0x00 { 4 } else { 2 };
+    self.reg_pc = (self.reg_pc + len) % MEMORY_SIZE;
+  }
+
+  /// Run the native routine in addr, in place of the machine code.
+  ///
+  /// The routine is taken out while running, so it can borrow the whole emulator.
+  fn sys(&mut self, addr: usize) -> Result<(), EmuError> {
+    let mut routine = self.routines.remove(addr).ok_or(EmuError::UnknownRoutine(addr))?;
+    let result = routine(self);
+    // Unless the routine registered another one in its own address.
+    if !self.routines.addresses().any(|a| a == addr) {
+      self.routines.insert(addr, routine);
+    }
+    result
   }
 
   /// Return from a subrotine, reducing the stack.
   fn ret(&mut self) -> Result<(), StackError> {
-    let ret_value = self.stack.pop()?;
+    let ret_value = if self.policy.wraps(FaultClass::Stack) {
+      self.stack.pop_wrapping()
+    } else {
+      self.stack.pop()?
+    };
     debug_assert!(ret_value < MEMORY_SIZE);
     self.reg_pc = ret_value;
     Ok(())
@@ -221,7 +516,11 @@ impl Emulator {
   /// Jump to the subroutine in addr, increasing the stack.
   fn call(&mut self, addr: usize) -> Result<(), StackError> {
     debug_assert!(addr < 0xFFF);
-    self.stack.push(self.reg_pc)?;
+    if self.policy.wraps(FaultClass::Stack) {
+      self.stack.push_wrapping(self.reg_pc);
+    } else {
+      self.stack.push(self.reg_pc)?;
+    }
     self.reg_pc = addr;
     Ok(())
   }
@@ -230,7 +529,7 @@ impl Emulator {
   fn se_inmm(&mut self, reg: usize, inmm: u8) {
     debug_assert!(reg < REG_SIZE);
     if self.reg[reg] == inmm {
-      self.reg_pc += 2;
+      self.skip_next();
     }
   }
 
@@ -238,7 +537,7 @@ impl Emulator {
   fn sne_inmm(&mut self, reg: usize, inmm: u8) {
     debug_assert!(reg < REG_SIZE);
     if self.reg[reg] != inmm {
-      self.reg_pc += 2;
+      self.skip_next();
     }
   }
 
@@ -247,7 +546,7 @@ impl Emulator {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
     if self.reg[reg_x] == self.reg[reg_y] {
-      self.reg_pc += 2;
+      self.skip_next();
     }
   }
 
@@ -256,7 +555,7 @@ impl Emulator {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
     if self.reg[reg_x] != self.reg[reg_y] {
-      self.reg_pc += 2;
+      self.skip_next();
     }
   }
 
@@ -278,19 +577,21 @@ impl Emulator {
     self.reg_i = inmm;
   }
 
-  /// Jump to the instrucction in addr reg V0 + inmm
+  /// Jump to the instrucction in addr reg V0 + inmm.
+  ///
+  /// CHIP-48 and SCHIP use reg X (the highest nibble of inmm) instead of V0. See Quirks::jump_vx.
   fn jump(&mut self, inmm: usize) -> Result<(), EmuError> {
     debug_assert!(inmm < 0xFFF);
-    let sum;
-    if *COMPATIBILITY.read().unwrap() {
+    let sum = if self.quirks.jump_vx {
       let reg = (((inmm as u16) & 0x0F00) >> 8) as usize;
-      sum = (self.reg[reg] as usize).wrapping_add(inmm);
-      
+      (self.reg[reg] as usize).wrapping_add(inmm)
     } else {
-      sum = (self.reg[0] as usize).wrapping_add(inmm);
-      
-    }
-    if sum > MEMORY_SIZE {
+      (self.reg[0] as usize).wrapping_add(inmm)
+    };
+    if self.policy.wraps(FaultClass::InvalidAddress) {
+      self.reg_pc = sum % MEMORY_SIZE;
+      Ok(())
+    } else if sum > MEMORY_SIZE {
       Err(EmuError::InvalidAddress(sum))
     } else {
       self.reg_pc = sum;
@@ -299,7 +600,7 @@ impl Emulator {
   }
 
   /// Generates a random value, binary AND with inmm, store the result in reg X.
-  fn rand(&mut self, reg: usize, inmm: u8, rng: &mut ThreadRng) {
+  fn rand<R: Rng + ?Sized>(&mut self, reg: usize, inmm: u8, rng: &mut R) {
     debug_assert!(reg < REG_SIZE);
     let random: u8 = rng.random();
     self.reg[reg] = random & inmm;
@@ -307,28 +608,53 @@ impl Emulator {
 
   /// Draw an inmm pixels tall sprite from the memory in location pointed by I, at the coordinates reg X and reg Y.
   /// All the pixels that are on the display will be turned off (if collission), setting reg 15 to 1.
-  fn display(&mut self, reg_x: usize, reg_y: usize, inmm: u8) {
+  ///
+  /// With inmm equal to 0, SUPER-CHIP draws a 16x16 sprite instead, two bytes per row.
+  ///
+  /// The starting coordinates always wrap around the display. The parts of the sprite beyond the
+  /// right and bottom edges are clipped, or wrap around to the other side. See Quirks::clipping.
+  /// A sprite past the end of the memory is an invalid address, unless the policy wraps it.
+  fn display(&mut self, reg_x: usize, reg_y: usize, inmm: u8) -> Result<(), EmuError> {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    let x = (self.reg[reg_x] % DISPLAY_WIDTH as u8) as usize;
-    let y = (self.reg[reg_y] % DISPLAY_HEIGHT as u8) as usize;
+    let (width, height) = (self.display.width(), self.display.height());
+    let x = self.reg[reg_x] as usize % width;
+    let y = self.reg[reg_y] as usize % height;
+    let (rows, row_bytes) = if inmm == 0 { (16, 2) } else { (inmm as usize, 1) };
     self.reg[REG_F] = 0;
-    for yline in 0..(inmm as usize) {
-      debug_assert!((yline + self.reg_i) < MEMORY_SIZE);
-      let sprite_byte = self.memory[self.reg_i + yline];
-      // For each bit.
-      for xline in 0..8 {
-        let sprite_bit = (sprite_byte & (0b10000000 >> xline)) > 0;
-        let abs_pos = ((x + xline), (y + yline));
-        // Collision (sprite bit and screen pixel both on)
-        if sprite_bit && self.display.get(abs_pos.0, abs_pos.1) {
-          self.display.set(abs_pos.0, abs_pos.1, false);
-          self.reg[REG_F] = 1;
-        } else if sprite_bit && !self.display.get(abs_pos.0, abs_pos.1) {
-          self.display.set(abs_pos.0, abs_pos.1, true);
+    // XO-CHIP: each selected plane takes its own sprite, one after the other in memory.
+    for (n, plane) in Display::selected(self.planes).enumerate() {
+      let sprite_addr = self.reg_i + n * rows * row_bytes;
+      for yline in 0..rows {
+        if self.quirks.clipping && y + yline >= height {
+          break;
+        }
+        let pos_y = (y + yline) % height;
+        let row_addr = sprite_addr + yline * row_bytes;
+        let high = self.memory[self.address(row_addr)?] as u16;
+        let sprite_row = if row_bytes == 2 {
+          (high << 8) | self.memory[self.address(row_addr + 1)?] as u16
+        } else {
+          high << 8
+        };
+        // For each bit.
+        for xline in 0..(row_bytes * 8) {
+          if self.quirks.clipping && x + xline >= width {
+            break;
+          }
+          let sprite_bit = (sprite_row & (0x8000 >> xline)) > 0;
+          let pos_x = (x + xline) % width;
+          // Collision (sprite bit and screen pixel both on)
+          if sprite_bit && self.display.get(plane, pos_x, pos_y) {
+            self.display.set(plane, pos_x, pos_y, false);
+            self.reg[REG_F] = 1;
+          } else if sprite_bit && !self.display.get(plane, pos_x, pos_y) {
+            self.display.set(plane, pos_x, pos_y, true);
+          }
         }
       }
     }
+    Ok(())
   }
 
   /// Set the value of reg X to the value of reg Y.
@@ -342,21 +668,31 @@ impl Emulator {
   fn or(&mut self, reg_x: usize, reg_y: usize) {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    self.reg[reg_x] = self.reg[reg_x] | self.reg[reg_y];
+    self.reg[reg_x] |= self.reg[reg_y];
+    self.logic_vf_reset();
   }
 
   /// The value of reg X will be the and between reg X and Y values.
   fn and(&mut self, reg_x: usize, reg_y: usize) {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    self.reg[reg_x] = self.reg[reg_x] & self.reg[reg_y];
+    self.reg[reg_x] &= self.reg[reg_y];
+    self.logic_vf_reset();
   }
 
   /// The value of reg X will be the xor between reg X and Y values.
   fn xor(&mut self, reg_x: usize, reg_y: usize) {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    self.reg[reg_x] = self.reg[reg_x] ^ self.reg[reg_y];
+    self.reg[reg_x] ^= self.reg[reg_y];
+    self.logic_vf_reset();
+  }
+
+  /// The original interpreter used VF as scratch in the logic operations, leaving it to 0.
+  fn logic_vf_reset(&mut self) {
+    if self.quirks.vf_reset {
+      self.reg[REG_F] = 0;
+    }
   }
 
   /// reg X = reg X + reg Y, setting reg 15 to 1 if overflow.
@@ -399,10 +735,11 @@ impl Emulator {
   }
 
   /// Set reg X = reg Y, then shift to the right(1), setting reg F to the bit out.
+  /// Without Quirks::shift_vy, reg X is shifted in place.
   fn right_shift(&mut self, reg_x: usize, reg_y: usize) {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    if *COMPATIBILITY.read().unwrap() {
+    if self.quirks.shift_vy {
       self.reg[reg_x] = self.reg[reg_y];
     }
     if self.reg[reg_x] & 0b00000001 == 1 {
@@ -410,14 +747,15 @@ impl Emulator {
     } else {
       self.reg[REG_F] = 0;
     }
-    self.reg[reg_x] = self.reg[reg_x] >> 1;
+    self.reg[reg_x] >>= 1;
   }
 
   /// Set reg X = reg Y, then shift to the left(1), setting reg F to the bit out.
+  /// Without Quirks::shift_vy, reg X is shifted in place.
   fn left_shift(&mut self, reg_x: usize, reg_y: usize) {
     debug_assert!(reg_x < REG_SIZE);
     debug_assert!(reg_y < REG_SIZE);
-    if *COMPATIBILITY.read().unwrap() {
+    if self.quirks.shift_vy {
       self.reg[reg_x] = self.reg[reg_y];
     }
     if (self.reg[reg_x] & 0b10000000) >> 7 == 1 {
@@ -425,37 +763,29 @@ impl Emulator {
     } else {
       self.reg[REG_F] = 0;
     }
-    self.reg[reg_x] = self.reg[reg_x] << 1;
+    self.reg[reg_x] <<= 1;
   }
 
   /// Skip the next instruction if the key in reg X is being pressed.
   fn skip_key(&mut self, reg: usize, keys: &[bool]) -> Result<(), EmuError> {
     debug_assert!(keys.len() == KEY_SIZE);
     debug_assert!(reg < REG_SIZE);
-    let value = self.reg[reg] as usize;
-    if value >= KEY_SIZE {
-      Err(EmuError::UnknownKey(value))
-    } else {
-      if keys[value] == true {
-        self.reg_pc += 2;
-      }
-      Ok(())
+    let value = self.key(self.reg[reg] as usize)?;
+    if keys[value] {
+      self.skip_next();
     }
+    Ok(())
   }
 
   /// Skip the next instruction if the key in reg X is being pressed.
   fn snkip_key(&mut self, reg: usize, keys: &[bool]) -> Result<(), EmuError> {
     debug_assert!(keys.len() == KEY_SIZE);
     debug_assert!(reg < REG_SIZE);
-    let value = self.reg[reg] as usize;
-    if value >= KEY_SIZE {
-      Err(EmuError::UnknownKey(value))
-    } else {
-      if keys[value] == false {
-        self.reg_pc += 2;
-      }
-      Ok(())
+    let value = self.key(self.reg[reg] as usize)?;
+    if !keys[value] {
+      self.skip_next();
     }
+    Ok(())
   }
 
   /// Set reg X to the value in delay reg.
@@ -479,15 +809,17 @@ impl Emulator {
   /// reg I will be reg I + reg X.
   ///
   /// In the original interpreter, VF was not affected, but in some modern yes.
-  /// Due some games relies in this behaviour, by default, will set VF in case of overflow.
+  /// Due some games relies in this behaviour, it can be enabled with Quirks::add_i_overflow.
   /// Overflow occurs when reg I > 0x0FFF
   fn add_to_index(&mut self, reg: usize) {
     debug_assert!(reg < REG_SIZE);
-    self.reg_i = self.reg_i + self.reg[reg] as usize;
-    if self.reg_i > 0x0FFF {
-      self.reg[REG_F] = 1;
-    } else {
-      self.reg[REG_F] = 0;
+    self.reg_i += self.reg[reg] as usize;
+    if self.quirks.add_i_overflow {
+      if self.reg_i > 0x0FFF {
+        self.reg[REG_F] = 1;
+      } else {
+        self.reg[REG_F] = 0;
+      }
     }
   }
 
@@ -495,21 +827,21 @@ impl Emulator {
   fn wait_key(&mut self, reg: usize, keys: &[bool]) -> Result<(), EmuError> {
     debug_assert!(keys.len() == KEY_SIZE);
     debug_assert!(reg < REG_SIZE);
-    let value = self.reg[reg] as usize;
-    if value >= KEY_SIZE {
-      Err(EmuError::UnknownKey(value))
-    } else {
-      if keys[value] == false {
-        self.reg_pc -= 2;
-      }
-      Ok(())
+    let value = self.key(self.reg[reg] as usize)?;
+    // Fetch the same instruction again, also if fetching it wrapped pc around the memory.
+    if !keys[value] {
+      self.reg_pc = (self.reg_pc + MEMORY_SIZE - 2) % MEMORY_SIZE;
     }
+    Ok(())
   }
 
   /// Set reg I to the start position of a font addr.
   fn load_font(&mut self, reg: usize) -> Result<(), EmuError> {
     debug_assert!(reg < REG_SIZE);
-    let value = self.reg[reg];
+    let mut value = self.reg[reg];
+    if self.policy.wraps(FaultClass::InvalidAddress) {
+      value %= FONTS.len() as u8;
+    }
     match value {
       | 0x0 => self.reg_i = FONT_START_ADDRESS,
       | 0x1 => self.reg_i = FONT_START_ADDRESS + FONT_SIZE,
@@ -532,21 +864,32 @@ impl Emulator {
     Ok(())
   }
 
+  /// Set reg I to the start position of a big font addr (SUPER-CHIP).
+  fn load_big_font(&mut self, reg: usize) -> Result<(), EmuError> {
+    debug_assert!(reg < REG_SIZE);
+    let mut value = self.reg[reg];
+    if self.policy.wraps(FaultClass::InvalidAddress) {
+      value %= BIG_FONTS.len() as u8;
+    }
+    if value as usize >= BIG_FONTS.len() {
+      return Err(EmuError::UnknownFont(value));
+    }
+    self.reg_i = BIG_FONT_START_ADDRESS + (BIG_FONT_SIZE * value as usize);
+    Ok(())
+  }
+
   /// Put the digits of the number stored in reg X in I, I+1 and I+2 (decimal).
   fn binary_dec(&mut self, reg: usize) -> Result<(), EmuError> {
     debug_assert!(reg < REG_SIZE);
     let value = self.reg[reg];
-    let pos = ((self.reg_i), (self.reg_i + 1), (self.reg_i + 2));
-    if pos.0 >= MEMORY_SIZE {
-      return Err(EmuError::InvalidAddress(pos.0));
-    } else if pos.1 >= MEMORY_SIZE {
-      return Err(EmuError::InvalidAddress(pos.1));
-    } else if pos.2 >= MEMORY_SIZE {
-      return Err(EmuError::InvalidAddress(pos.2));
-    }
+    let pos =
+      (self.address(self.reg_i)?, self.address(self.reg_i + 1)?, self.address(self.reg_i + 2)?);
     self.memory[pos.0] = (value / 100) % 10; // More significant digit.
     self.memory[pos.1] = (value / 10) % 10;
     self.memory[pos.2] = value % 10; // Less significant digit.
+    for pos in [pos.0, pos.1, pos.2] {
+      self.cache.invalidate(pos, 1);
+    }
     Ok(())
   }
 
@@ -555,18 +898,19 @@ impl Emulator {
   /// In modern interpreters, the reg I won't change,
   /// while in the original CHIP-8 will change to the value reg I + x + 1
   ///
-  /// I made this option toggeable for a bit better compatibility with some roms.
+  /// I made this option toggeable for a bit better compatibility with some roms (Quirks::increment_i).
+  /// CHIP-48 changed it to reg I + x (Quirks::increment_i_x).
   fn store_mem(&mut self, reg: usize) -> Result<(), EmuError> {
     debug_assert!(reg < REG_SIZE);
     for r in 0..=reg {
-      let pos = self.reg_i + r;
-      if pos >= MEMORY_SIZE {
-        return Err(EmuError::InvalidAddress(pos));
-      }
+      let pos = self.address(self.reg_i + r)?;
       self.memory[pos] = self.reg[r];
+      self.cache.invalidate(pos, 1);
     }
-    if !*COMPATIBILITY.read().unwrap() {
+    if self.quirks.increment_i {
       self.reg_i = self.reg_i + reg + 1;
+    } else if self.quirks.increment_i_x {
+      self.reg_i += reg;
     }
     Ok(())
   }
@@ -576,28 +920,533 @@ impl Emulator {
   fn load_mem(&mut self, reg: usize) -> Result<(), EmuError> {
     debug_assert!(reg < REG_SIZE);
     for r in 0..=reg {
-      let pos = self.reg_i + r;
-      if pos >= MEMORY_SIZE {
-        return Err(EmuError::InvalidAddress(pos));
-      }
+      let pos = self.address(self.reg_i + r)?;
       self.reg[r] = self.memory[pos];
     }
-    if !*COMPATIBILITY.read().unwrap() {
+    if self.quirks.increment_i {
       self.reg_i = self.reg_i + reg + 1;
+    } else if self.quirks.increment_i_x {
+      self.reg_i += reg;
     }
     Ok(())
   }
+
+  /// Store the values of the reg (from X to Y, both included) into memory, starting in reg I.
+  /// If X > Y, the registers are stored in reverse order. I is not modified (XO-CHIP).
+  fn store_range(&mut self, reg_x: usize, reg_y: usize) -> Result<(), EmuError> {
+    debug_assert!(reg_x < REG_SIZE);
+    debug_assert!(reg_y < REG_SIZE);
+    let len = reg_x.abs_diff(reg_y);
+    self.address(self.reg_i + len)?;
+    for n in 0..=len {
+      let r = if reg_x <= reg_y { reg_x + n } else { reg_x - n };
+      self.memory[(self.reg_i + n) % MEMORY_SIZE] = self.reg[r];
+    }
+    self.cache.invalidate(self.reg_i % MEMORY_SIZE, len + 1);
+    Ok(())
+  }
+
+  /// Load the values of the mem into the reg (from X to Y, both included), starting in reg I.
+  /// If X > Y, the registers are loaded in reverse order. I is not modified (XO-CHIP).
+  fn load_range(&mut self, reg_x: usize, reg_y: usize) -> Result<(), EmuError> {
+    debug_assert!(reg_x < REG_SIZE);
+    debug_assert!(reg_y < REG_SIZE);
+    let len = reg_x.abs_diff(reg_y);
+    self.address(self.reg_i + len)?;
+    for n in 0..=len {
+      let r = if reg_x <= reg_y { reg_x + n } else { reg_x - n };
+      self.reg[r] = self.memory[(self.reg_i + n) % MEMORY_SIZE];
+    }
+    Ok(())
+  }
+
+  /// Set reg I to the 16 bit address stored in the next word, skipping it (XO-CHIP).
+  fn load_long_i(&mut self) -> Result<(), EmuError> {
+    self.reg_i = self.fetch()? as usize;
+    Ok(())
+  }
+
+  /// Select the bit planes affected by the drawing, clearing and scrolling (XO-CHIP).
+  fn plane(&mut self, mask: u8) {
+    debug_assert!(mask < 0x10);
+    self.planes = mask & 0b11;
+  }
+
+  /// Load the 16 bytes of audio pattern, starting in reg I (XO-CHIP).
+  fn load_audio(&mut self) -> Result<(), EmuError> {
+    self.address(self.reg_i + AUDIO_PATTERN_SIZE - 1)?;
+    let pattern = std::array::from_fn(|n| self.memory[(self.reg_i + n) % MEMORY_SIZE]);
+    self.audio_pattern = Some(pattern);
+    Ok(())
+  }
+
+  /// Set the pitch register to the value in reg X (XO-CHIP).
+  fn load_pitch(&mut self, reg: usize) {
+    debug_assert!(reg < REG_SIZE);
+    self.pitch = self.reg[reg];
+  }
+
+  /// Stop the execution of the program (SUPER-CHIP).
+  fn exit(&mut self) {
+    self.exited = true;
+  }
+
+  /// Store the values of the reg (from 0 to X, both included) in the RPL user flags.
+  fn store_flags(&mut self, reg: usize) {
+    debug_assert!(reg < REG_SIZE);
+    self.rpl[..=reg].copy_from_slice(&self.reg[..=reg]);
+  }
+
+  /// Load the values of the RPL user flags (from 0 to X, both included) into the reg.
+  fn load_flags(&mut self, reg: usize) {
+    debug_assert!(reg < REG_SIZE);
+    self.reg[..=reg].copy_from_slice(&self.rpl[..=reg]);
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use crate::emulator::{Emulator, START_ADDR};
+  use crate::decoder::{Instruction, decode};
+  use crate::emulator::error::EmuError;
+  use crate::emulator::{AUDIO_PATTERN_SIZE, Emulator, MEMORY_SIZE, Quirks, REG_F, START_ADDR};
+  use crate::fault::{FaultAction, FaultClass, FaultPolicy};
 
   #[test]
   fn test_load_program() {
     let vec = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
-    let mut emu = Emulator::new();
+    let mut emu = Emulator::new(Quirks::default());
     emu.load_program(&vec).unwrap();
     assert_eq!(emu.memory[START_ADDR], vec[0]);
   }
+
+  #[test]
+  fn test_quirk_shift_vy() {
+    let mut emu = Emulator::new(Quirks { shift_vy: true, ..Quirks::default() });
+    emu.reg[0x1] = 0b0000_0001;
+    emu.reg[0x2] = 0b1000_0000;
+    emu.right_shift(0x1, 0x2);
+    assert_eq!(emu.reg[0x1], 0b0100_0000);
+    assert_eq!(emu.reg[REG_F], 0);
+
+    let mut emu = Emulator::new(Quirks { shift_vy: false, ..Quirks::default() });
+    emu.reg[0x1] = 0b0000_0001;
+    emu.reg[0x2] = 0b1000_0000;
+    emu.right_shift(0x1, 0x2);
+    assert_eq!(emu.reg[0x1], 0);
+    assert_eq!(emu.reg[REG_F], 1);
+  }
+
+  #[test]
+  fn test_quirk_jump_vx() {
+    let mut emu = Emulator::new(Quirks { jump_vx: true, ..Quirks::default() });
+    emu.reg[0x0] = 0x10;
+    emu.reg[0x3] = 0x20;
+    emu.jump(0x300).unwrap();
+    assert_eq!(emu.reg_pc, 0x320);
+
+    let mut emu = Emulator::new(Quirks { jump_vx: false, ..Quirks::default() });
+    emu.reg[0x0] = 0x10;
+    emu.reg[0x3] = 0x20;
+    emu.jump(0x300).unwrap();
+    assert_eq!(emu.reg_pc, 0x310);
+  }
+
+  #[test]
+  fn test_quirk_increment_i() {
+    let mut emu = Emulator::new(Quirks { increment_i: true, ..Quirks::default() });
+    emu.reg_i = 0x300;
+    emu.store_mem(0x3).unwrap();
+    assert_eq!(emu.reg_i, 0x304);
+
+    let mut emu = Emulator::new(Quirks { increment_i: false, ..Quirks::default() });
+    emu.reg_i = 0x300;
+    emu.load_mem(0x3).unwrap();
+    assert_eq!(emu.reg_i, 0x300);
+
+    let mut emu = Emulator::new(Quirks::CHIP_48);
+    emu.reg_i = 0x300;
+    emu.load_mem(0x3).unwrap();
+    assert_eq!(emu.reg_i, 0x303);
+    assert_ne!(Quirks::CHIP_48, Quirks::SCHIP);
+  }
+
+  #[test]
+  fn test_quirk_vf_reset() {
+    let mut emu = Emulator::new(Quirks { vf_reset: true, ..Quirks::default() });
+    emu.reg[REG_F] = 1;
+    emu.or(0x1, 0x2);
+    assert_eq!(emu.reg[REG_F], 0);
+
+    let mut emu = Emulator::new(Quirks { vf_reset: false, ..Quirks::default() });
+    emu.reg[REG_F] = 1;
+    emu.xor(0x1, 0x2);
+    assert_eq!(emu.reg[REG_F], 1);
+  }
+
+  #[test]
+  fn test_quirk_add_i_overflow() {
+    let mut emu = Emulator::new(Quirks { add_i_overflow: true, ..Quirks::default() });
+    emu.reg_i = 0xFFF;
+    emu.reg[0x1] = 1;
+    emu.add_to_index(0x1);
+    assert_eq!(emu.reg[REG_F], 1);
+
+    let mut emu = Emulator::new(Quirks { add_i_overflow: false, ..Quirks::default() });
+    emu.reg_i = 0xFFF;
+    emu.reg[0x1] = 1;
+    emu.add_to_index(0x1);
+    assert_eq!(emu.reg[REG_F], 0);
+  }
+
+  /// Draw a full 8x4 sprite at (x, y), returning the emulator and the lit pixels.
+  fn draw_block(quirks: Quirks, hires: bool, x: u8, y: u8) -> (Emulator, usize) {
+    let mut emu = Emulator::new(quirks);
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    if hires {
+      emu.execute(Instruction::Hires, &mut rng, &keys).unwrap();
+    }
+    emu.memory[0x300..0x304].fill(0xFF);
+    emu.reg_i = 0x300;
+    emu.reg[0x0] = x;
+    emu.reg[0x1] = y;
+    emu.execute(Instruction::Display(0x0, 0x1, 4), &mut rng, &keys).unwrap();
+    let lit = (0..emu.display_height())
+      .flat_map(|y| (0..emu.display_width()).map(move |x| (x, y)))
+      .filter(|(x, y)| emu.display_val(*x, *y) != 0)
+      .count();
+    (emu, lit)
+  }
+
+  #[test]
+  fn test_quirk_clipping() {
+    let clip = Quirks { clipping: true, ..Quirks::default() };
+    // Inside the display, touching the top left corner.
+    let (emu, lit) = draw_block(clip, false, 0, 0);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(7, 3), 1);
+    // Right edge, bottom edge and bottom right corner.
+    let (emu, lit) = draw_block(clip, false, 60, 10);
+    assert_eq!(lit, 16);
+    assert_eq!(emu.display_val(63, 13), 1);
+    assert_eq!(emu.display_val(0, 10), 0);
+    let (emu, lit) = draw_block(clip, false, 10, 30);
+    assert_eq!(lit, 16);
+    assert_eq!(emu.display_val(17, 31), 1);
+    assert_eq!(emu.display_val(10, 0), 0);
+    let (emu, lit) = draw_block(clip, false, 62, 31);
+    assert_eq!(lit, 2);
+    assert_eq!(emu.display_val(63, 31), 1);
+    assert_eq!(emu.display_val(0, 0), 0);
+    // Top right and bottom left corners.
+    let (emu, lit) = draw_block(clip, false, 63, 0);
+    assert_eq!(lit, 4);
+    assert_eq!(emu.display_val(63, 3), 1);
+    let (emu, lit) = draw_block(clip, false, 0, 31);
+    assert_eq!(lit, 8);
+    assert_eq!(emu.display_val(7, 31), 1);
+    // The starting coordinates still wrap.
+    let (emu, lit) = draw_block(clip, false, 64 + 60, 32 + 30);
+    assert_eq!(lit, 8);
+    assert_eq!(emu.display_val(60, 30), 1);
+    // Also in high resolution.
+    let (emu, lit) = draw_block(clip, true, 124, 62);
+    assert_eq!(lit, 8);
+    assert_eq!(emu.display_val(127, 63), 1);
+  }
+
+  #[test]
+  fn test_quirk_wrapping() {
+    let wrap = Quirks { clipping: false, ..Quirks::default() };
+    // Right edge, bottom edge and bottom right corner.
+    let (emu, lit) = draw_block(wrap, false, 60, 10);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(63, 13), 1);
+    assert_eq!(emu.display_val(3, 13), 1);
+    assert_eq!(emu.display_val(4, 13), 0);
+    let (emu, lit) = draw_block(wrap, false, 10, 30);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(17, 31), 1);
+    assert_eq!(emu.display_val(17, 1), 1);
+    assert_eq!(emu.display_val(17, 2), 0);
+    let (emu, lit) = draw_block(wrap, false, 62, 31);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(63, 31), 1);
+    assert_eq!(emu.display_val(0, 0), 1);
+    assert_eq!(emu.display_val(5, 2), 1);
+    assert_eq!(emu.display_val(6, 2), 0);
+    // Top right and bottom left corners.
+    let (emu, lit) = draw_block(wrap, false, 63, 0);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(6, 3), 1);
+    let (emu, lit) = draw_block(wrap, false, 0, 31);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(7, 2), 1);
+    // Also in high resolution.
+    let (emu, lit) = draw_block(wrap, true, 124, 62);
+    assert_eq!(lit, 32);
+    assert_eq!(emu.display_val(3, 1), 1);
+  }
+
+  #[test]
+  fn test_wrap_collision() {
+    let mut emu = Emulator::new(Quirks { clipping: false, ..Quirks::default() });
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    emu.memory[0x300] = 0xFF;
+    emu.reg_i = 0x300;
+    emu.reg[0x0] = 60;
+    emu.execute(Instruction::Display(0x0, 0x1, 1), &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg[REG_F], 0);
+    // Only the wrapped part overlaps.
+    emu.reg[0x0] = 2;
+    emu.execute(Instruction::Display(0x0, 0x1, 1), &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg[REG_F], 1);
+    assert_eq!(emu.display_val(1, 0), 1);
+    assert_eq!(emu.display_val(2, 0), 0);
+    assert_eq!(emu.display_val(4, 0), 1);
+  }
+
+  #[test]
+  fn test_fault_wrap() {
+    let mut emu = Emulator::new(Quirks::default());
+    let mut policy = FaultPolicy::default();
+    for class in [FaultClass::InvalidAddress, FaultClass::Stack, FaultClass::UnknownKey] {
+      policy.set(class, FaultAction::Wrap).unwrap();
+    }
+    emu.set_fault_policy(policy);
+    emu.reg[..2].copy_from_slice(&[1, 2]);
+    emu.reg_i = MEMORY_SIZE - 1;
+    emu.store_mem(0x1).unwrap();
+    assert_eq!((emu.memory[MEMORY_SIZE - 1], emu.memory[0]), (1, 2));
+    // The 17th call overwrites the bottom of the stack.
+    for _ in 0..17 {
+      emu.call(0x300).unwrap();
+    }
+    assert_eq!(emu.stack.depth(), 1);
+    emu.reg[0x3] = 0x15;
+    let mut keys = [false; 16];
+    keys[0x5] = true;
+    emu.reg_pc = 0x300;
+    emu.skip_key(0x3, &keys).unwrap();
+    assert_eq!(emu.reg_pc, 0x302);
+
+    // Without wrapping, the same instructions fault.
+    let mut emu = Emulator::new(Quirks::default());
+    emu.reg_i = MEMORY_SIZE - 1;
+    assert_eq!(emu.store_mem(0x1), Err(EmuError::InvalidAddress(MEMORY_SIZE)));
+    emu.reg[0x3] = 0x15;
+    assert_eq!(emu.skip_key(0x3, &keys), Err(EmuError::UnknownKey(0x15)));
+  }
+
+  #[test]
+  fn test_store_range_past_memory() {
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    let mut policy = FaultPolicy::default();
+    policy.set(FaultClass::InvalidAddress, FaultAction::Wrap).unwrap();
+    emu.set_fault_policy(policy);
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    // LD I, LONG 0xFFFF; ADD I, V0 leaves I past the memory, with the cache in use.
+    emu.load_program(&[0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x1E]).unwrap();
+    emu.reg[0x0] = 3;
+    for _ in 0..2 {
+      let instr = emu.fetch_decoded().unwrap();
+      emu.execute(instr, &mut rng, &keys).unwrap();
+    }
+    assert_eq!(emu.reg_i, MEMORY_SIZE + 2);
+    // Writes over the first instruction, decoded and cached.
+    emu.reg_i = MEMORY_SIZE + START_ADDR;
+    let version = emu.code_version();
+    emu.execute(Instruction::StRange(0x0, 0x1), &mut rng, &keys).unwrap();
+    assert_eq!(emu.memory[START_ADDR], 3);
+    assert_ne!(emu.code_version(), version);
+  }
+
+  #[test]
+  fn test_sprite_past_memory() {
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    // LD I, LONG 0xFFFE; DRW V0, V1, 5
+    let program = [0xF0, 0x00, 0xFF, 0xFE, 0xD0, 0x15];
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    emu.load_program(&program).unwrap();
+    emu.memory[MEMORY_SIZE - 2..].fill(0xFF);
+    let load = decode(emu.fetch().unwrap()).unwrap();
+    emu.execute(load, &mut rng, &keys).unwrap();
+    let draw = decode(emu.fetch().unwrap()).unwrap();
+    assert_eq!(emu.execute(draw, &mut rng, &keys), Err(EmuError::InvalidAddress(MEMORY_SIZE)));
+
+    // Wrapping, the last 3 rows come from the start of the memory.
+    let mut policy = FaultPolicy::default();
+    policy.set(FaultClass::InvalidAddress, FaultAction::Wrap).unwrap();
+    emu.set_fault_policy(policy);
+    emu.clear_display();
+    emu.execute(draw, &mut rng, &keys).unwrap();
+    for row in 0..5 {
+      let byte = emu.memory[(MEMORY_SIZE - 2 + row) % MEMORY_SIZE];
+      let pixels = (0..8).fold(0, |acc, x| (acc << 1) | emu.display_val(x, row));
+      assert_eq!(pixels, byte);
+    }
+  }
+
+  #[test]
+  fn test_sys_routines() {
+    let mut emu = Emulator::new(Quirks::default());
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    emu.register_routine(0x123, |emu| {
+      emu.registers_mut()[0x0] += 1;
+      emu.set_reg_i(0x300);
+      Ok(())
+    });
+    emu.execute(Instruction::Sys(0x123), &mut rng, &keys).unwrap();
+    emu.execute(Instruction::Sys(0x123), &mut rng, &keys).unwrap();
+    assert_eq!((emu.reg[0x0], emu.reg_i), (2, 0x300));
+    assert_eq!(emu.routines().addresses().collect::<Vec<_>>(), [0x123]);
+
+    let result = emu.execute(Instruction::Sys(0x456), &mut rng, &keys);
+    assert_eq!(result, Err(EmuError::UnknownRoutine(0x456)));
+    assert!(emu.unregister_routine(0x123).is_some());
+    let result = emu.execute(Instruction::Sys(0x123), &mut rng, &keys);
+    assert_eq!(result, Err(EmuError::UnknownRoutine(0x123)));
+  }
+
+  #[test]
+  fn test_decode_cache() {
+    let mut emu = Emulator::new(Quirks::default());
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    // The loop rewrites its first instruction (LD V0, 0x01 into LD V0, 0x02) and jumps back.
+    #[rustfmt::skip]
+    emu.load_program(&[
+      0x60, 0x01, // 0x200: LD V0, 0x01
+      0xA2, 0x00, // 0x202: LD I, 0x200
+      0x61, 0x60, // 0x204: LD V1, 0x60
+      0x62, 0x02, // 0x206: LD V2, 0x02
+      0x51, 0x22, // 0x208: SAVE V1, V2
+      0x12, 0x00, // 0x20A: JP 0x200
+    ]).unwrap();
+    let mut step = |emu: &mut Emulator| {
+      let instr = emu.fetch_decoded().unwrap();
+      emu.execute(instr, &mut rng, &keys).unwrap();
+      instr
+    };
+    assert_eq!(step(&mut emu), Instruction::LoadInmm(0x0, 0x01));
+    for _ in 0..5 {
+      step(&mut emu);
+    }
+    assert_eq!(step(&mut emu), Instruction::LoadInmm(0x0, 0x02));
+    assert_eq!(emu.reg[0x0], 2);
+    // Changes through memory_mut are seen too.
+    emu.memory_mut()[0x203] = 0x10;
+    assert_eq!(step(&mut emu), Instruction::LoadI(0x210));
+
+    // The last instruction of the memory is cached, and wraps the program counter as fetch.
+    emu.memory_mut()[MEMORY_SIZE - 2..].copy_from_slice(&[0x60, 0x03]);
+    for _ in 0..2 {
+      emu.reg_pc = MEMORY_SIZE - 2;
+      assert_eq!(emu.fetch_decoded(), Ok(Instruction::LoadInmm(0x0, 0x03)));
+      assert_eq!(emu.reg_pc, 0);
+    }
+  }
+
+  #[test]
+  fn test_big_sprite() {
+    let mut emu = Emulator::new(Quirks::default());
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    emu.execute(Instruction::Hires, &mut rng, &keys).unwrap();
+    emu.memory[0x300..0x320].fill(0xFF);
+    emu.reg_i = 0x300;
+    emu.execute(Instruction::Display(0x0, 0x1, 0), &mut rng, &keys).unwrap();
+    assert_eq!(emu.display_val(15, 15), 1);
+    assert_eq!(emu.display_val(16, 15), 0);
+    assert_eq!(emu.display_val(15, 16), 0);
+    assert_eq!(emu.reg[REG_F], 0);
+  }
+
+  #[test]
+  fn test_audio_pattern() {
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    let mut rng = rand::rng();
+    assert_eq!(emu.audio_pattern(), None);
+    emu.memory[0x300..0x310].copy_from_slice(&[0xAA; AUDIO_PATTERN_SIZE]);
+    emu.reg_i = 0x300;
+    emu.execute(Instruction::LoadAudio, &mut rng, &[false; 16]).unwrap();
+    assert_eq!(emu.audio_pattern(), Some(&[0xAA; AUDIO_PATTERN_SIZE]));
+
+    let mut other = Emulator::new(Quirks::XO_CHIP);
+    other.load_state(&emu.save_state()).unwrap();
+    assert_eq!(other.audio_pattern(), Some(&[0xAA; AUDIO_PATTERN_SIZE]));
+  }
+
+  #[test]
+  fn test_rpl_flags() {
+    let mut emu = Emulator::new(Quirks::default());
+    emu.reg[..4].copy_from_slice(&[1, 2, 3, 4]);
+    emu.store_flags(0x2);
+    emu.reg[..4].fill(0);
+    emu.load_flags(0x3);
+    assert_eq!(emu.reg[..4], [1, 2, 3, 0]);
+  }
+
+  #[test]
+  fn test_skip_long_i() {
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    emu.load_program(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0]).unwrap();
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+    emu.execute(instr, &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg_pc, START_ADDR + 6);
+    emu.reg_pc = START_ADDR + 2;
+    let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+    emu.execute(instr, &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg_i, 0x1234);
+    assert_eq!(emu.reg_pc, START_ADDR + 6);
+
+    // At the end of the memory, the skips and the key wait wrap pc as fetch does.
+    emu.memory[MEMORY_SIZE - 4..].copy_from_slice(&[0x30, 0x00, 0xF0, 0x00]);
+    emu.reg_pc = MEMORY_SIZE - 4;
+    let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+    emu.execute(instr, &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg_pc, 2);
+    emu.memory[MEMORY_SIZE - 2..].copy_from_slice(&[0xF0, 0x0A]);
+    emu.reg_pc = MEMORY_SIZE - 2;
+    let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+    assert_eq!(emu.reg_pc, 0);
+    emu.execute(instr, &mut rng, &keys).unwrap();
+    assert_eq!(emu.reg_pc, MEMORY_SIZE - 2);
+  }
+
+  #[test]
+  fn test_range() {
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    emu.reg[..4].copy_from_slice(&[1, 2, 3, 4]);
+    emu.reg_i = 0x300;
+    emu.store_range(0x3, 0x1).unwrap();
+    assert_eq!(emu.memory[0x300..0x303], [4, 3, 2]);
+    assert_eq!(emu.reg_i, 0x300);
+    emu.load_range(0x5, 0x7).unwrap();
+    assert_eq!(emu.reg[0x5..0x8], [4, 3, 2]);
+  }
+
+  #[test]
+  fn test_planes() {
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    emu.memory[0x300] = 0x80;
+    emu.memory[0x301] = 0xC0;
+    emu.reg_i = 0x300;
+    emu.execute(Instruction::Plane(0b11), &mut rng, &keys).unwrap();
+    emu.execute(Instruction::Display(0x0, 0x0, 1), &mut rng, &keys).unwrap();
+    assert_eq!(emu.display_val(0, 0), 3);
+    assert_eq!(emu.display_val(1, 0), 2);
+    emu.execute(Instruction::Plane(0b10), &mut rng, &keys).unwrap();
+    emu.execute(Instruction::Cls, &mut rng, &keys).unwrap();
+    assert_eq!(emu.display_val(0, 0), 1);
+    assert_eq!(emu.display_val(1, 0), 0);
+  }
 }
@@ -0,0 +1,93 @@
+//! cache.rs
+//! Instructions already decoded, by address.
+
+use crate::decoder::Instruction;
+use crate::emulator::MEMORY_SIZE;
+
+/// Decoding the same words again and again is most of the work of a cycle, so each instruction
+/// is kept decoded in the address it was fetched from.
+///
+/// The entries are only valid while the memory does not change: every write to the memory
+/// invalidates the instructions that overlap it (the ones starting in the address written, or
+/// in the previous one). The entries are allocated on the first insert, so emulators that only
+/// fetch and decode by themselves don't pay for it.
+///
+/// The version changes every time decoded instructions are dropped, so whatever is built over
+/// them (the blocks of the block engine) knows when to drop it too.
+#[derive(Clone, Debug, Default)]
+pub struct DecodeCache {
+  entries: Vec<Option<Instruction>>,
+  version: u64,
+}
+
+impl DecodeCache {
+  pub fn get(&self, addr: usize) -> Option<Instruction> {
+    self.entries.get(addr).copied().flatten()
+  }
+
+  pub fn insert(&mut self, addr: usize, instr: Instruction) {
+    if self.entries.is_empty() {
+      self.entries = vec![None; MEMORY_SIZE];
+    }
+    self.entries[addr] = Some(instr);
+  }
+
+  pub fn version(&self) -> u64 {
+    self.version
+  }
+
+  /// Invalidate the instructions overlapping the len bytes written from addr. The address wraps
+  /// around the memory.
+  pub fn invalidate(&mut self, addr: usize, len: usize) {
+    if self.entries.is_empty() || len == 0 {
+      return;
+    }
+    let addr = addr % MEMORY_SIZE;
+    let start = addr.saturating_sub(1);
+    let end = (addr + len).min(MEMORY_SIZE);
+    let entries = &mut self.entries[start..end];
+    if entries.iter().any(Option::is_some) {
+      entries.fill(None);
+      self.version += 1;
+    }
+    // A write that wrapped around the memory.
+    if addr + len > MEMORY_SIZE {
+      self.invalidate(0, addr + len - MEMORY_SIZE);
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.entries.clear();
+    self.version += 1;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::decoder::Instruction;
+  use crate::emulator::MEMORY_SIZE;
+  use crate::emulator::cache::DecodeCache;
+
+  #[test]
+  fn test_invalidate() {
+    let mut cache = DecodeCache::default();
+    assert_eq!(cache.get(0x200), None);
+    for addr in [0x0, 0x200, 0x201, 0x202, MEMORY_SIZE - 1] {
+      cache.insert(addr, Instruction::Cls);
+    }
+    cache.invalidate(0x202, 1);
+    assert_eq!(cache.version(), 1);
+    assert_eq!(cache.get(0x200), Some(Instruction::Cls));
+    assert_eq!(cache.get(0x201), None);
+    assert_eq!(cache.get(0x202), None);
+    // Wrapping around the end of the memory.
+    cache.invalidate(MEMORY_SIZE - 1, 2);
+    assert_eq!(cache.get(MEMORY_SIZE - 1), None);
+    assert_eq!(cache.get(0x0), None);
+    assert_eq!(cache.get(0x200), Some(Instruction::Cls));
+    // Writes over nothing decoded keep the version.
+    let version = cache.version();
+    cache.invalidate(0x300, 0x10);
+    assert_eq!(cache.version(), version);
+  }
+}
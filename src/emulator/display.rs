@@ -1,22 +1,37 @@
 //! display.rs
 //! Display of the CHIP-8
 
-use crate::emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH};
+
+/// Number of bit planes (XO-CHIP).
+pub const PLANES: usize = 2;
 
 /// The original CHIP-8 uses a 64x32 pixel, monochrome (on/off).
+/// SUPER-CHIP added a 128x64 high resolution mode, switchable at runtime.
+/// XO-CHIP added a second bit plane, so each pixel can take 4 colors.
+///
+/// My implementation internally uses a single array per plane, big enough for the high resolution,
+/// avoid double indirection. For this, internally implements a function to convert (x, y)
+/// coordinates into an absolute position, depending on the current width.
 ///
-/// My implementation internally uses a single 2048 array, avoid double indirection.
-/// For this, internally implements a function to convert (x, y) coordinates into an absolute position.
+/// Most operations receive a plane mask (bit 0 the first plane, bit 1 the second),
+/// as XO-CHIP only draws, clears and scrolls the selected planes.
 ///
 /// Remember the (0, 0) is in the top left corner.
 #[derive(Debug)]
 pub struct Display {
-  array: [bool; DISPLAY_HEIGHT * DISPLAY_WIDTH],
+  planes: [[bool; HIRES_DISPLAY_HEIGHT * HIRES_DISPLAY_WIDTH]; PLANES],
+  width: usize,
+  height: usize,
 }
 
 impl Default for Display {
   fn default() -> Self {
-    Self { array: [false; DISPLAY_HEIGHT * DISPLAY_WIDTH] }
+    Self {
+      planes: [[false; HIRES_DISPLAY_HEIGHT * HIRES_DISPLAY_WIDTH]; PLANES],
+      width: DISPLAY_WIDTH,
+      height: DISPLAY_HEIGHT,
+    }
   }
 }
 
@@ -26,46 +41,183 @@ impl Display {
     Self::default()
   }
 
+  /// Current width in pixels.
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Current height in pixels.
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Return true if the display is in the 128x64 high resolution mode.
+  pub fn is_hires(&self) -> bool {
+    self.width == HIRES_DISPLAY_WIDTH
+  }
+
+  /// Switch between the 64x32 and the 128x64 resolutions, clearing all the planes.
+  pub fn set_hires(&mut self, hires: bool) {
+    if hires {
+      self.width = HIRES_DISPLAY_WIDTH;
+      self.height = HIRES_DISPLAY_HEIGHT;
+    } else {
+      self.width = DISPLAY_WIDTH;
+      self.height = DISPLAY_HEIGHT;
+    }
+    self.clear(0b11);
+  }
+
   /// Convert an (x, y) into an absolute position.
   ///
-  /// Considering each row has DISPLAY_WIDTH positions, to each row multiply y * DISPLAY_WIDTH.
+  /// Considering each row has width positions, to each row multiply y * width.
   /// For indexing inside the row, just need to sum the position x.
   ///
-  /// Example:\
+  /// Example (64x32):\
   /// (30, 30) -> (30 * 64) + 30 = 1950\
   /// (0, 15) -> (15 * 64) + 0 = 960\
   /// (5, 0) -> (0 * 64) + 5 = 5\
   /// (63, 63) -> (31 * 64) + 63 = 2047
-  fn transform_cords(x: usize, y: usize) -> usize {
-    debug_assert!((x < DISPLAY_WIDTH) && (y < DISPLAY_HEIGHT));
-    (y * DISPLAY_WIDTH) + x
+  fn transform_cords(&self, x: usize, y: usize) -> usize {
+    debug_assert!((x < self.width) && (y < self.height));
+    (y * self.width) + x
+  }
+
+  /// Set a value in a pixel of a plane.
+  pub fn set(&mut self, plane: usize, x: usize, y: usize, v: bool) {
+    debug_assert!(plane < PLANES);
+    let pos = self.transform_cords(x, y);
+    self.planes[plane][pos] = v
+  }
+
+  /// Get the current value in a pixel of a plane.
+  pub fn get(&self, plane: usize, x: usize, y: usize) -> bool {
+    debug_assert!(plane < PLANES);
+    self.planes[plane][self.transform_cords(x, y)]
+  }
+
+  /// Get the color index of a pixel, combining all the planes (0 to 3).
+  pub fn color(&self, x: usize, y: usize) -> u8 {
+    let pos = self.transform_cords(x, y);
+    (0..PLANES).fold(0, |acc, p| acc | ((self.planes[p][pos] as u8) << p))
+  }
+
+  /// Set all the bits in the selected planes to 0.
+  pub fn clear(&mut self, mask: u8) {
+    for plane in Self::selected(mask) {
+      self.planes[plane].fill(false);
+    }
+  }
+
+  /// Scroll the selected planes n pixels down. The new rows at the top are off.
+  pub fn scroll_down(&mut self, mask: u8, n: usize) {
+    let n = n.min(self.height);
+    let row = self.width;
+    let len = row * self.height;
+    for plane in Self::selected(mask) {
+      let array = &mut self.planes[plane];
+      array.copy_within(0..(len - n * row), n * row);
+      array[..(n * row)].fill(false);
+    }
+  }
+
+  /// Scroll the selected planes n pixels up. The new rows at the bottom are off.
+  pub fn scroll_up(&mut self, mask: u8, n: usize) {
+    let n = n.min(self.height);
+    let row = self.width;
+    let len = row * self.height;
+    for plane in Self::selected(mask) {
+      let array = &mut self.planes[plane];
+      array.copy_within((n * row)..len, 0);
+      array[(len - n * row)..len].fill(false);
+    }
   }
 
-  /// Set a value in a pixel.
-  pub fn set(&mut self, x: usize, y: usize, v: bool) {
-    self.array[Self::transform_cords(x, y)] = v
+  /// Scroll the selected planes n pixels to the right. The new columns at the left are off.
+  pub fn scroll_right(&mut self, mask: u8, n: usize) {
+    let n = n.min(self.width);
+    let row = self.width;
+    for plane in Self::selected(mask) {
+      let array = &mut self.planes[plane];
+      for y in 0..self.height {
+        let start = y * row;
+        array.copy_within(start..(start + row - n), start + n);
+        array[start..(start + n)].fill(false);
+      }
+    }
   }
 
-  /// Get the current value in a pixel.
-  pub fn get(&self, x: usize, y: usize) -> bool {
-    self.array[Self::transform_cords(x, y)]
+  /// Scroll the selected planes n pixels to the left. The new columns at the right are off.
+  pub fn scroll_left(&mut self, mask: u8, n: usize) {
+    let n = n.min(self.width);
+    let row = self.width;
+    for plane in Self::selected(mask) {
+      let array = &mut self.planes[plane];
+      for y in 0..self.height {
+        let start = y * row;
+        array.copy_within((start + n)..(start + row), start);
+        array[(start + row - n)..(start + row)].fill(false);
+      }
+    }
   }
 
-  /// Set all the bits in the display to 0.
-  pub fn clear(&mut self) {
-    self.array.fill(false);
+  /// Iterator over the plane indexes selected in mask.
+  pub fn selected(mask: u8) -> impl Iterator<Item = usize> {
+    (0..PLANES).filter(move |p| mask & (1 << p) != 0)
   }
 }
 
 #[cfg(test)]
 mod test {
-  use crate::emulator::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH, Display};
+  use crate::emulator::display::{
+    DISPLAY_HEIGHT, DISPLAY_WIDTH, Display, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH,
+  };
 
   #[test]
   fn test_transform_cords() {
     let mut display = Display::new();
-    display.set(DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1, true);
-    assert_eq!(display.get(DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1), true);
-    assert_eq!(display.array[DISPLAY_HEIGHT * DISPLAY_WIDTH - 1], true);
+    display.set(0, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1, true);
+    assert!(display.get(0, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1));
+    assert!(display.planes[0][DISPLAY_HEIGHT * DISPLAY_WIDTH - 1]);
+  }
+
+  #[test]
+  fn test_hires() {
+    let mut display = Display::new();
+    display.set(0, 0, 0, true);
+    display.set_hires(true);
+    assert!(!display.get(0, 0, 0));
+    display.set(0, HIRES_DISPLAY_WIDTH - 1, HIRES_DISPLAY_HEIGHT - 1, true);
+    assert!(display.planes[0][HIRES_DISPLAY_HEIGHT * HIRES_DISPLAY_WIDTH - 1]);
+  }
+
+  #[test]
+  fn test_scroll() {
+    let mut display = Display::new();
+    display.set(0, 10, 10, true);
+    display.scroll_down(0b01, 4);
+    assert!(display.get(0, 10, 14));
+    display.scroll_right(0b01, 4);
+    assert!(display.get(0, 14, 14));
+    display.scroll_left(0b01, 8);
+    assert!(display.get(0, 6, 14));
+    display.scroll_up(0b01, 2);
+    assert!(display.get(0, 6, 12));
+    assert_eq!(display.planes[0].iter().filter(|p| **p).count(), 1);
+  }
+
+  #[test]
+  fn test_planes() {
+    let mut display = Display::new();
+    display.set(0, 1, 1, true);
+    display.set(1, 1, 1, true);
+    display.set(1, 2, 1, true);
+    assert_eq!(display.color(1, 1), 3);
+    assert_eq!(display.color(2, 1), 2);
+    display.scroll_down(0b10, 1);
+    assert_eq!(display.color(1, 1), 1);
+    display.clear(0b01);
+    assert_eq!(display.color(1, 1), 0);
+    assert_eq!(display.color(2, 2), 2);
   }
 }
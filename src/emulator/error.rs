@@ -3,12 +3,17 @@
 
 use std::{error, fmt};
 
+use crate::emulator::StackError;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EmuError {
   InvalidAddress(usize),
   ProgramTooBig(usize),
   UnknownFont(u8),
   UnknownKey(usize),
+  Stack(StackError),
+  /// 0nnn to an address without native routine.
+  UnknownRoutine(usize),
 }
 
 impl fmt::Display for EmuError {
@@ -18,8 +23,16 @@ impl fmt::Display for EmuError {
       | Self::ProgramTooBig(n) => write!(f, "Not possible to load the program, too big: {}", n),
       | Self::UnknownFont(x) => write!(f, "Indexing an unkown font value: {}", x),
       | Self::UnknownKey(x) => write!(f, "Trying to access an unkown key: {}", x),
+      | Self::Stack(err) => write!(f, "{}", err),
+      | Self::UnknownRoutine(n) => write!(f, "No native routine in 0x{:03X}", n),
     }
   }
 }
 
 impl error::Error for EmuError {}
+
+impl From<StackError> for EmuError {
+  fn from(err: StackError) -> Self {
+    Self::Stack(err)
+  }
+}
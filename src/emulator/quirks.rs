@@ -0,0 +1,120 @@
+//! quirks.rs
+//! Behaviour differences between the CHIP-8 interpreters.
+
+/// Each CHIP-8 interpreter resolved a few instructions in its own way, and programs written for
+/// one of them usually rely on those details. Every field toggles one of those behaviours.
+///
+/// The presets follow the usual platforms:
+/// - COSMAC VIP: the original interpreter from 1977.
+/// - CHIP-48: the HP-48 calculator port, origin of most "modern" behaviours.
+/// - SCHIP: SUPER-CHIP 1.1, also for the HP-48, that no longer moves I in Fx55/Fx65.
+/// - XO-CHIP: the Octo extension, closer to the VIP in most aspects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+  /// 8xy6/8xyE copy VY into VX before shifting. Otherwise VX is shifted in place.
+  pub shift_vy: bool,
+  /// Bnnn jumps to VX + nnn (X being the highest nibble of nnn). Otherwise to V0 + nnn.
+  pub jump_vx: bool,
+  /// Fx55/Fx65 leave I pointing to the next address after the last register (I + X + 1).
+  pub increment_i: bool,
+  /// 8xy1/8xy2/8xy3 set VF to 0 after the logic operation.
+  pub vf_reset: bool,
+  /// Dxyn waits for the vertical blank interrupt before drawing, so one sprite per frame.
+  pub display_wait: bool,
+  /// Sprites are clipped at the edges of the screen. Otherwise they wrap around.
+  pub clipping: bool,
+  /// Fx1E sets VF to 1 when I goes beyond 0xFFF, 0 otherwise. No preset sets it (the Amiga
+  /// interpreter did, and some games rely on it), so it is enabled on top of one.
+  pub add_i_overflow: bool,
+  /// Fx55/Fx65 leave I pointing to the last register stored (I + X), as CHIP-48 did by mistake.
+  /// Ignored with increment_i.
+  pub increment_i_x: bool,
+}
+
+impl Quirks {
+  /// Original COSMAC VIP interpreter.
+  pub const COSMAC_VIP: Self = Self {
+    shift_vy: true,
+    jump_vx: false,
+    increment_i: true,
+    vf_reset: true,
+    display_wait: true,
+    clipping: true,
+    add_i_overflow: false,
+    increment_i_x: false,
+  };
+
+  /// CHIP-48 for the HP-48 calculators.
+  pub const CHIP_48: Self = Self {
+    shift_vy: false,
+    jump_vx: true,
+    increment_i: false,
+    vf_reset: false,
+    display_wait: false,
+    clipping: true,
+    add_i_overflow: false,
+    increment_i_x: true,
+  };
+
+  /// SUPER-CHIP 1.1.
+  pub const SCHIP: Self = Self {
+    shift_vy: false,
+    jump_vx: true,
+    increment_i: false,
+    vf_reset: false,
+    display_wait: false,
+    clipping: true,
+    add_i_overflow: false,
+    increment_i_x: false,
+  };
+
+  /// XO-CHIP, as implemented by Octo.
+  pub const XO_CHIP: Self = Self {
+    shift_vy: true,
+    jump_vx: false,
+    increment_i: true,
+    vf_reset: false,
+    display_wait: false,
+    clipping: false,
+    add_i_overflow: false,
+    increment_i_x: false,
+  };
+
+  /// Pack the quirks in a byte, one bit per field (in declaration order, from bit 0).
+  pub fn bits(&self) -> u8 {
+    [
+      self.shift_vy,
+      self.jump_vx,
+      self.increment_i,
+      self.vf_reset,
+      self.display_wait,
+      self.clipping,
+      self.add_i_overflow,
+      self.increment_i_x,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |acc, (n, q)| acc | ((*q as u8) << n))
+  }
+
+  /// Unpack the quirks packed by bits.
+  pub fn from_bits(bits: u8) -> Self {
+    let bit = |n: u8| bits & (1 << n) != 0;
+    Self {
+      shift_vy: bit(0),
+      jump_vx: bit(1),
+      increment_i: bit(2),
+      vf_reset: bit(3),
+      display_wait: bit(4),
+      clipping: bit(5),
+      add_i_overflow: bit(6),
+      increment_i_x: bit(7),
+    }
+  }
+}
+
+impl Default for Quirks {
+  fn default() -> Self {
+    Self::COSMAC_VIP
+  }
+}
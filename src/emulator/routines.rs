@@ -0,0 +1,40 @@
+//! routines.rs
+//! Native routines, run in place of the machine code called with 0nnn.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::emulator::{Emulator, error::EmuError};
+
+/// Rust function run by 0nnn, with the emulator to read and change its state.
+pub type Routine = Box<dyn FnMut(&mut Emulator) -> Result<(), EmuError> + Send>;
+
+/// The COSMAC VIP interpreter jumped with 0nnn to the 1802 machine code in nnn. The routines
+/// replace that code: hosts register a routine in an address, and programs call it as the
+/// original ones did.
+#[derive(Default)]
+pub struct Routines {
+  routines: BTreeMap<usize, Routine>,
+}
+
+impl Routines {
+  /// Register the routine in the address, returning the previous one.
+  pub fn insert(&mut self, addr: usize, routine: Routine) -> Option<Routine> {
+    self.routines.insert(addr, routine)
+  }
+
+  pub fn remove(&mut self, addr: usize) -> Option<Routine> {
+    self.routines.remove(&addr)
+  }
+
+  /// Addresses with a routine, in order.
+  pub fn addresses(&self) -> impl Iterator<Item = usize> + '_ {
+    self.routines.keys().copied()
+  }
+}
+
+impl fmt::Debug for Routines {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.addresses().map(|addr| format!("0x{:03X}", addr))).finish()
+  }
+}
@@ -0,0 +1,303 @@
+//! savestate.rs
+//! Snapshot and restore of the complete machine.
+
+pub mod error;
+
+use crate::emulator::display::{Display, PLANES};
+use crate::emulator::savestate::error::StateError;
+use crate::emulator::{AUDIO_PATTERN_SIZE, Emulator, FLAGS_SIZE, MEMORY_SIZE, REG_SIZE, Stack};
+use crate::rng::SerializableRng;
+
+/// Identifies the save states of this emulator.
+const MAGIC: [u8; 4] = *b"F8ST";
+/// Format version, increased each time the layout changes.
+const VERSION: u16 = 1;
+
+/// Layout of a save state (version 1), all the numbers in little endian:
+/// - Magic "F8ST" and format version (u16).
+/// - Quirks bits (u8) and memory size (u32), to reject incompatible states.
+/// - Memory.
+/// - V0 to VF, I (u32), PC (u32), delay and sound timers.
+/// - Stack depth (u8) and the values in the stack (u32 each).
+/// - RPL user flags and selected planes.
+/// - Whether an audio pattern was loaded (u8), the pattern (zeros if not) and the pitch.
+/// - Exited flag (u8).
+/// - High resolution flag (u8) and each plane of the display, 1 bit per pixel.
+/// - Optionally, the random generator feeding the emulator (SerializableRng::save), up to the end.
+impl Emulator {
+  /// Serialize the complete state of the machine, without a random generator.
+  pub fn save_state(&self) -> Vec<u8> {
+    let mut out = Vec::with_capacity(MEMORY_SIZE + 4096);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.push(self.quirks.bits());
+    out.extend_from_slice(&(MEMORY_SIZE as u32).to_le_bytes());
+    out.extend_from_slice(&self.memory);
+    out.extend_from_slice(&self.reg);
+    out.extend_from_slice(&(self.reg_i as u32).to_le_bytes());
+    out.extend_from_slice(&(self.reg_pc as u32).to_le_bytes());
+    out.push(self.reg_delay);
+    out.push(self.reg_sound);
+    out.push(self.stack.depth() as u8);
+    for value in self.stack.as_slice() {
+      out.extend_from_slice(&(*value as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&self.rpl);
+    out.push(self.planes);
+    out.push(self.audio_pattern.is_some() as u8);
+    out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+    out.push(self.pitch);
+    out.push(self.exited as u8);
+    out.push(self.display.is_hires() as u8);
+    for plane in 0..PLANES {
+      let mut byte = 0;
+      let mut bit = 0;
+      for y in 0..self.display.height() {
+        for x in 0..self.display.width() {
+          byte |= (self.display.get(plane, x, y) as u8) << (7 - bit);
+          bit += 1;
+          if bit == 8 {
+            out.push(byte);
+            byte = 0;
+            bit = 0;
+          }
+        }
+      }
+    }
+    out
+  }
+
+  /// Serialize the complete state of the machine, and the random generator feeding it after it.
+  pub fn save_state_with_rng<R: SerializableRng>(&self, rng: &R) -> Vec<u8> {
+    let mut out = self.save_state();
+    out.extend_from_slice(&rng.save());
+    out
+  }
+
+  /// Restore a state produced by save_state or save_state_with_rng, ignoring the random
+  /// generator if any. On error, the emulator is not modified.
+  ///
+  /// The state must come from an emulator with the same quirks and memory size.
+  pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+    let (emu, _) = self.read_state(data)?;
+    self.replace(emu);
+    Ok(())
+  }
+
+  /// Restore a state produced by save_state_with_rng, and the random generator with it.
+  /// On error, neither of them is modified. A state saved without the random generator is
+  /// rejected with MissingRng (it can still be restored with load_state).
+  pub fn load_state_with_rng<R: SerializableRng>(
+    &mut self, data: &[u8], rng: &mut R,
+  ) -> Result<(), StateError> {
+    let (emu, trailer) = self.read_state(data)?;
+    if trailer.is_empty() {
+      return Err(StateError::MissingRng);
+    }
+    *rng = R::restore(trailer).ok_or(StateError::Corrupted)?;
+    self.replace(emu);
+    Ok(())
+  }
+
+  /// Read a state into a new emulator, returning it with the bytes after it (the random
+  /// generator, if saved).
+  fn read_state<'a>(&self, data: &'a [u8]) -> Result<(Emulator, &'a [u8]), StateError> {
+    let mut r = Reader { data, pos: 0 };
+    if r.bytes(MAGIC.len())? != MAGIC {
+      return Err(StateError::InvalidMagic);
+    }
+    let version = r.u16()?;
+    if version != VERSION {
+      return Err(StateError::UnsupportedVersion(version));
+    }
+    let quirks = r.u8()?;
+    if quirks != self.quirks.bits() {
+      return Err(StateError::IncompatiblePlatform { expected: self.quirks.bits(), found: quirks });
+    }
+    let memory_size = r.u32()? as usize;
+    if memory_size != MEMORY_SIZE {
+      return Err(StateError::IncompatibleMemory { expected: MEMORY_SIZE, found: memory_size });
+    }
+
+    // Everything is read into a new emulator, and only replaced at the end.
+    let mut emu = Emulator { quirks: self.quirks, policy: self.policy, ..Emulator::default() };
+    emu.memory.copy_from_slice(r.bytes(MEMORY_SIZE)?);
+    emu.reg.copy_from_slice(r.bytes(REG_SIZE)?);
+    emu.reg_i = r.u32()? as usize;
+    emu.reg_pc = r.u32()? as usize;
+    if emu.reg_pc >= MEMORY_SIZE {
+      return Err(StateError::Corrupted);
+    }
+    emu.reg_delay = r.u8()?;
+    emu.reg_sound = r.u8()?;
+    let depth = r.u8()? as usize;
+    let mut values = Vec::with_capacity(depth);
+    for _ in 0..depth {
+      let value = r.u32()? as usize;
+      if value >= MEMORY_SIZE {
+        return Err(StateError::Corrupted);
+      }
+      values.push(value);
+    }
+    emu.stack = Stack::from_slice(&values).map_err(|_| StateError::Corrupted)?;
+    emu.rpl.copy_from_slice(r.bytes(FLAGS_SIZE)?);
+    emu.planes = r.u8()?;
+    let loaded = r.u8()? != 0;
+    let pattern = r.bytes(AUDIO_PATTERN_SIZE)?.try_into().unwrap();
+    emu.audio_pattern = loaded.then_some(pattern);
+    emu.pitch = r.u8()?;
+    emu.exited = r.u8()? != 0;
+    emu.display = Display::new();
+    emu.display.set_hires(r.u8()? != 0);
+    let (width, height) = (emu.display.width(), emu.display.height());
+    for plane in 0..PLANES {
+      let packed = r.bytes(width * height / 8)?;
+      for y in 0..height {
+        for x in 0..width {
+          let n = y * width + x;
+          emu.display.set(plane, x, y, packed[n / 8] & (0x80 >> (n % 8)) != 0);
+        }
+      }
+    }
+    emu.refresh = true;
+    Ok((emu, &data[r.pos..]))
+  }
+
+  /// Replace the machine with one read by read_state, keeping what belongs to the host.
+  fn replace(&mut self, mut emu: Emulator) {
+    // The native routines belong to the host, not to the state.
+    emu.routines = std::mem::take(&mut self.routines);
+    // The decoded instructions are dropped, but with a new version for the blocks built on them.
+    emu.cache = std::mem::take(&mut self.cache);
+    emu.cache.clear();
+    *self = emu;
+  }
+}
+
+/// Small cursor over the state bytes.
+struct Reader<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+    let end = self.pos + n;
+    if end > self.data.len() {
+      return Err(StateError::Truncated);
+    }
+    let slice = &self.data[self.pos..end];
+    self.pos = end;
+    Ok(slice)
+  }
+
+  fn u8(&mut self) -> Result<u8, StateError> {
+    Ok(self.bytes(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16, StateError> {
+    Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+  }
+
+  fn u32(&mut self) -> Result<u32, StateError> {
+    Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::decoder::Instruction;
+  use crate::emulator::savestate::error::StateError;
+  use crate::emulator::{Emulator, MEMORY_SIZE, Quirks, REG_SIZE};
+  use crate::rng::{SeededRng, SerializableRng};
+
+  use rand::RngExt;
+
+  fn sample() -> (Emulator, SeededRng) {
+    let mut emu = Emulator::new(Quirks::SCHIP);
+    let mut rng = SeededRng::new(7);
+    let keys = [false; 16];
+    emu.load_program(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xFF, 0x60, 0x07]).unwrap();
+    for _ in 0..3 {
+      let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+      emu.execute(instr, &mut rng, &keys).unwrap();
+    }
+    emu.reg_i = 0x50;
+    emu.execute(Instruction::Display(0x0, 0x1, 5), &mut rng, &keys).unwrap();
+    emu.reg_delay = 30;
+    rng.random::<u64>();
+    (emu, rng)
+  }
+
+  #[test]
+  fn test_roundtrip() {
+    let (emu, mut rng) = sample();
+    let state = emu.save_state_with_rng(&rng);
+    assert_eq!(state[..state.len() - 16], emu.save_state());
+    assert_eq!(state[state.len() - 16..], rng.save());
+    let mut other = Emulator::new(Quirks::SCHIP);
+    let mut other_rng = SeededRng::new(0);
+    other.load_state_with_rng(&state, &mut other_rng).unwrap();
+    assert_eq!(other.save_state_with_rng(&other_rng), state);
+    assert_eq!(other_rng.seed(), 7);
+    assert_eq!(other_rng.random::<u64>(), rng.random::<u64>());
+    assert_eq!(other.reg_pc, emu.reg_pc);
+    assert_eq!(other.reg[0], 0x07);
+    assert_eq!(other.stack.as_slice(), [0x202]);
+    assert_eq!(other.display_width(), 128);
+    assert_eq!(other.display_val(7, 0), 1);
+
+    // Without the random generator.
+    let mut other = Emulator::new(Quirks::SCHIP);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), emu.save_state());
+    other.load_state(&emu.save_state()).unwrap();
+    assert_eq!(other.save_state(), emu.save_state());
+  }
+
+  #[test]
+  fn test_rejects() {
+    let (emu, rng) = sample();
+    let state = emu.save_state_with_rng(&rng);
+    let mut rng = SeededRng::new(0);
+    let mut other = Emulator::new(Quirks::COSMAC_VIP);
+    assert!(matches!(
+      other.load_state_with_rng(&state, &mut rng),
+      Err(StateError::IncompatiblePlatform { .. })
+    ));
+
+    let mut other = Emulator::new(Quirks::SCHIP);
+    let mut bad = state.clone();
+    bad[0] = b'X';
+    assert_eq!(other.load_state_with_rng(&bad, &mut rng), Err(StateError::InvalidMagic));
+
+    let mut bad = state.clone();
+    bad[4] = 99;
+    assert_eq!(other.load_state_with_rng(&bad, &mut rng), Err(StateError::UnsupportedVersion(99)));
+
+    let mut bad = state.clone();
+    bad[7..11].copy_from_slice(&4096u32.to_le_bytes());
+    assert_eq!(
+      other.load_state_with_rng(&bad, &mut rng),
+      Err(StateError::IncompatibleMemory { expected: 0x10000, found: 4096 })
+    );
+
+    let short = &state[..state.len() - 17];
+    assert_eq!(other.load_state(short), Err(StateError::Truncated));
+    let short = &state[..state.len() - 1];
+    assert_eq!(other.load_state_with_rng(short, &mut rng), Err(StateError::Corrupted));
+    assert_eq!(other.load_state_with_rng(&emu.save_state(), &mut rng), Err(StateError::MissingRng));
+    assert_eq!(other.reg_pc, crate::emulator::START_ADDR);
+    assert_eq!(rng, SeededRng::new(0));
+
+    // The program counter and the return addresses must be inside the memory.
+    let pc = 6 + 1 + 4 + MEMORY_SIZE + REG_SIZE + 4;
+    let mut bad = state.clone();
+    bad[pc..pc + 4].copy_from_slice(&(MEMORY_SIZE as u32).to_le_bytes());
+    assert_eq!(other.load_state(&bad), Err(StateError::Corrupted));
+    let mut bad = state.clone();
+    bad[pc + 7..pc + 11].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(other.load_state(&bad), Err(StateError::Corrupted));
+    assert_eq!(other.reg_pc, crate::emulator::START_ADDR);
+  }
+}
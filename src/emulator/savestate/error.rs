@@ -0,0 +1,39 @@
+//! error.rs
+//! Possible errors restoring a save state.
+
+use std::{error::Error, fmt};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+  InvalidMagic,
+  UnsupportedVersion(u16),
+  IncompatiblePlatform { expected: u8, found: u8 },
+  IncompatibleMemory { expected: usize, found: usize },
+  Truncated,
+  Corrupted,
+  MissingRng,
+}
+
+impl fmt::Display for StateError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::InvalidMagic => write!(f, "Not a ferret-8 save state"),
+      | Self::UnsupportedVersion(v) => write!(f, "Unsupported save state version: {}", v),
+      | Self::IncompatiblePlatform { expected, found } => write!(
+        f,
+        "Save state from an incompatible platform (quirks 0b{:08b}, running 0b{:08b})",
+        found, expected
+      ),
+      | Self::IncompatibleMemory { expected, found } => write!(
+        f,
+        "Save state with an incompatible memory size ({} bytes, running {} bytes)",
+        found, expected
+      ),
+      | Self::Truncated => write!(f, "Save state truncated"),
+      | Self::Corrupted => write!(f, "Save state corrupted"),
+      | Self::MissingRng => write!(f, "Save state without the random generator"),
+    }
+  }
+}
+
+impl Error for StateError {}
@@ -27,6 +27,16 @@ impl Stack {
     Self::default()
   }
 
+  /// Creates a new Stack with the values, from the bottom to the top.
+  /// If there are more than the stack can hold, returns an error (overflow).
+  pub fn from_slice(values: &[usize]) -> Result<Self, StackError> {
+    let mut stack = Self::new();
+    for value in values {
+      stack.push(*value)?;
+    }
+    Ok(stack)
+  }
+
   /// Increase the stack pointer and then push a new value in the stack. If it is full, returns an error (overflow).
   pub fn push(&mut self, v: usize) -> Result<(), StackError> {
     if self.stack_pointer >= STACK_SIZE {
@@ -38,6 +48,34 @@ impl Stack {
     }
   }
 
+  /// Push, and when the stack is full start again from the bottom, overwriting it.
+  pub fn push_wrapping(&mut self, v: usize) {
+    if self.stack_pointer >= STACK_SIZE {
+      self.stack_pointer = 0;
+    }
+    self.array[self.stack_pointer] = v;
+    self.stack_pointer += 1;
+  }
+
+  /// Pop, and when the stack is empty continue from the top.
+  pub fn pop_wrapping(&mut self) -> usize {
+    if self.stack_pointer == 0 {
+      self.stack_pointer = STACK_SIZE;
+    }
+    self.stack_pointer -= 1;
+    self.array[self.stack_pointer]
+  }
+
+  /// Number of values in the stack.
+  pub fn depth(&self) -> usize {
+    self.stack_pointer
+  }
+
+  /// Values in the stack, from the bottom to the top.
+  pub fn as_slice(&self) -> &[usize] {
+    &self.array[..self.stack_pointer]
+  }
+
   /// Reduce the stack pointer and return the value it points. If it is empty, returns an error (underflow).
   pub fn pop(&mut self) -> Result<usize, StackError> {
     if self.stack_pointer == 0 {
@@ -79,4 +117,13 @@ mod test {
     }
     assert_eq!(stack.pop(), Err(StackError::Underflow));
   }
+
+  #[test]
+  fn test_as_slice() {
+    let mut stack = Stack::new();
+    stack.push(0x200).unwrap();
+    stack.push(0x300).unwrap();
+    assert_eq!(stack.depth(), 2);
+    assert_eq!(stack.as_slice(), [0x200, 0x300]);
+  }
 }
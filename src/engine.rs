@@ -0,0 +1,257 @@
+//! engine.rs
+//! Execution engines: the interpreter, one instruction at a time, or straight-line blocks.
+
+use crate::decoder::Instruction;
+use crate::emulator::{Emulator, MEMORY_SIZE};
+use crate::fault::{self, Fault};
+use crate::scheduler::Scheduler;
+
+use rand::prelude::*;
+
+/// Most instructions in a block.
+pub const MAX_BLOCK_LEN: usize = 64;
+
+/// How the program is executed. Both give exactly the same results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+  /// Fetch, decode and execute each instruction.
+  #[default]
+  Interp,
+  /// Run straight-line blocks of instructions decoded in advance.
+  Block,
+}
+
+/// Executes the program with an engine, spending the budget of a scheduler.
+///
+/// The block engine splits the program in blocks: the instructions from an address up to the
+/// first one that can change the control flow (a jump, call, return, skip...). Each block is
+/// decoded once, and then runs without fetching nor decoding, nor updating the program counter
+/// until its end.
+///
+/// The blocks are built over the cache of decoded instructions of the emulator, and all of them
+/// are dropped when the program writes over an instruction already decoded (see
+/// Emulator::code_version). A block also stops right after such a write, so the rest of it is
+/// decoded again. The blocks belong to a single emulator: use an executor for each one.
+#[derive(Debug, Default)]
+pub struct Executor {
+  engine: Engine,
+  /// The block starting in each address, if built. Allocated on the first block.
+  blocks: Vec<Option<Box<[Instruction]>>>,
+  /// Addresses with a block, to drop them.
+  starts: Vec<usize>,
+  /// Code version of the emulator when the blocks were built.
+  version: u64,
+}
+
+impl Executor {
+  pub fn new(engine: Engine) -> Self {
+    Self { engine, ..Self::default() }
+  }
+
+  pub fn engine(&self) -> Engine {
+    self.engine
+  }
+
+  /// Execute the next instruction, or with the block engine the next block, until the end of
+  /// the block or of the budget of the scheduler. Return the number of instructions executed.
+  ///
+  /// On a fault, the emulator is left as the interpreter would have left it: the program counter
+  /// points to the instruction after the faulting one.
+  pub fn step<R: Rng + ?Sized>(
+    &mut self, emu: &mut Emulator, rng: &mut R, keys: &[bool], scheduler: &mut Scheduler,
+  ) -> Result<usize, Fault> {
+    match self.engine {
+      | Engine::Interp => interp_step(emu, rng, keys, scheduler),
+      | Engine::Block => self.block_step(emu, rng, keys, scheduler),
+    }
+  }
+
+  fn block_step<R: Rng + ?Sized>(
+    &mut self, emu: &mut Emulator, rng: &mut R, keys: &[bool], scheduler: &mut Scheduler,
+  ) -> Result<usize, Fault> {
+    if emu.code_version() != self.version {
+      self.clear();
+      self.version = emu.code_version();
+    }
+    let start = emu.reg_pc();
+    if self.blocks.get(start).is_none_or(Option::is_none) {
+      match build_block(emu) {
+        | Some(block) => self.insert(start, block),
+        // Not even the first instruction can be decoded (or cached): the interpreter faults
+        // where it should.
+        | None => return interp_step(emu, rng, keys, scheduler),
+      }
+      // Building the block may have cached the instructions, but never drops any.
+      debug_assert_eq!(emu.code_version(), self.version);
+    }
+    let block = self.blocks[start].as_deref().unwrap();
+
+    // The program counter is only needed by the last instruction (the one changing the control
+    // flow) and by the faults, so it is only set for them.
+    for (n, instr) in block.iter().enumerate() {
+      let next = start + 2 * (n + 1);
+      let last = n + 1 == block.len();
+      if last {
+        emu.set_reg_pc(next);
+      }
+      scheduler.charge(instr, emu);
+      if let Err(err) = emu.execute(*instr, rng, keys) {
+        emu.set_reg_pc(next);
+        return Err(Fault::new(err.into(), emu, next - 2, Some(*instr)));
+      }
+      if last {
+        break;
+      }
+      // The program wrote over itself: the rest of the block may have changed.
+      if !scheduler.has_budget() || emu.code_version() != self.version {
+        emu.set_reg_pc(next);
+        return Ok(n + 1);
+      }
+    }
+    Ok(block.len())
+  }
+
+  fn insert(&mut self, start: usize, block: Box<[Instruction]>) {
+    if self.blocks.is_empty() {
+      self.blocks = vec![None; MEMORY_SIZE];
+    }
+    self.blocks[start] = Some(block);
+    self.starts.push(start);
+  }
+
+  /// Drop all the blocks.
+  fn clear(&mut self) {
+    for start in self.starts.drain(..) {
+      self.blocks[start] = None;
+    }
+  }
+}
+
+/// Fetch, decode and execute the next instruction.
+fn interp_step<R: Rng + ?Sized>(
+  emu: &mut Emulator, rng: &mut R, keys: &[bool], scheduler: &mut Scheduler,
+) -> Result<usize, Fault> {
+  let pc = emu.reg_pc();
+  let instr = fault::fetch_decode(emu)?;
+  scheduler.charge(&instr, emu);
+  fault::execute(emu, pc, instr, rng, keys)?;
+  Ok(1)
+}
+
+/// Decode the block starting in the program counter, through the cache of decoded instructions.
+/// It ends before an instruction that can't be decoded, or that wraps around the memory (those
+/// are never cached). None if the block is empty.
+fn build_block(emu: &mut Emulator) -> Option<Box<[Instruction]>> {
+  let start = emu.reg_pc();
+  let mut block = Vec::new();
+  while block.len() < MAX_BLOCK_LEN && emu.reg_pc() + 1 < MEMORY_SIZE {
+    let Ok(instr) = emu.fetch_decoded() else {
+      break;
+    };
+    block.push(instr);
+    if ends_block(&instr) {
+      break;
+    }
+  }
+  emu.set_reg_pc(start);
+  (!block.is_empty()).then(|| block.into_boxed_slice())
+}
+
+/// Return true if the instruction can change the control flow, or reads the program counter.
+fn ends_block(instr: &Instruction) -> bool {
+  matches!(
+    instr,
+    Instruction::Sys(_)
+      | Instruction::Return
+      | Instruction::Exit
+      | Instruction::SetPC(_)
+      | Instruction::Call(_)
+      | Instruction::SeInmm(..)
+      | Instruction::SneInmm(..)
+      | Instruction::SeReg(..)
+      | Instruction::SneReg(..)
+      | Instruction::Jump(_)
+      | Instruction::Skip(_)
+      | Instruction::Snkip(_)
+      | Instruction::WaitKey(_)
+      | Instruction::LoadLongI
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use crate::assembler::assemble;
+  use crate::emulator::{Emulator, Quirks};
+  use crate::engine::{Engine, Executor};
+  use crate::rng::SeededRng;
+  use crate::scheduler::Scheduler;
+  use crate::timing::Timing;
+
+  /// Scheduler with a budget of n instructions.
+  fn budget(n: usize) -> Scheduler {
+    let mut scheduler = Scheduler::new(n * 60, Timing::Flat);
+    scheduler.start_tick();
+    scheduler
+  }
+
+  #[test]
+  fn test_blocks() {
+    let mut emu = Emulator::new(Quirks::default());
+    let program = "
+      start:  LD V0, 1
+              ADD V0, 2
+              JP next
+      next:   LD V1, V0
+              SE V1, 3
+              JP start
+              CLS
+      ";
+    emu.load_program(&assemble(program).unwrap()).unwrap();
+    let mut executor = Executor::new(Engine::Block);
+    let mut rng = SeededRng::new(0);
+    let mut scheduler = budget(100);
+    let mut step = |emu: &mut Emulator| executor.step(emu, &mut rng, &[false; 16], &mut scheduler);
+    assert_eq!(step(&mut emu).unwrap(), 3);
+    assert_eq!(emu.reg_pc(), 0x206);
+    assert_eq!(step(&mut emu).unwrap(), 2);
+    assert_eq!(emu.reg_pc(), 0x20C);
+    assert_eq!(emu.registers()[0x1], 3);
+  }
+
+  #[test]
+  fn test_block_budget() {
+    let mut emu = Emulator::new(Quirks::default());
+    emu.load_program(&assemble("LD V0, 1\nLD V1, 2\nLD V2, 3\nJP 0x200").unwrap()).unwrap();
+    let mut executor = Executor::new(Engine::Block);
+    let mut scheduler = budget(2);
+    assert_eq!(
+      executor.step(&mut emu, &mut SeededRng::new(0), &[false; 16], &mut scheduler).unwrap(),
+      2
+    );
+    assert_eq!(emu.reg_pc(), 0x204);
+    assert_eq!(emu.registers()[0x2], 0);
+  }
+
+  #[test]
+  fn test_self_modifying_block() {
+    let mut emu = Emulator::new(Quirks::default());
+    // Writes LD V2, 0x07 over the instruction after the store, in the same block.
+    let program = "
+              LD V0, 0x62
+              LD V1, 0x07
+              LD I, patch
+              LD [I], V1
+      patch:  LD V2, 0
+              JP patch
+      ";
+    emu.load_program(&assemble(program).unwrap()).unwrap();
+    let mut executor = Executor::new(Engine::Block);
+    let mut rng = SeededRng::new(0);
+    let mut scheduler = budget(100);
+    let mut step = |emu: &mut Emulator| executor.step(emu, &mut rng, &[false; 16], &mut scheduler);
+    assert_eq!(step(&mut emu).unwrap(), 4);
+    assert_eq!(emu.reg_pc(), 0x208);
+    assert_eq!(step(&mut emu).unwrap(), 2);
+    assert_eq!(emu.registers()[0x2], 0x07);
+  }
+}
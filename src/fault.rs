@@ -0,0 +1,394 @@
+//! fault.rs
+//! Faults of the running program, with the state of the machine where they happened.
+
+pub mod error;
+
+use std::collections::HashSet;
+use std::{fmt, str::FromStr};
+
+use crate::decoder::Instruction;
+use crate::emulator::{Emulator, REG_SIZE, error::EmuError};
+use crate::fault::error::{FaultKind, PolicyError};
+
+use rand::prelude::*;
+
+/// An error of the program, with the context to find its cause: the address and opcode of the
+/// faulting instruction, and the registers and stack depth after it.
+///
+/// Its Display renders the crash report:
+/// ```text
+/// ferret-8 crash report
+/// Error: Stack overflow
+/// PC: 0x0206  Opcode: 0x2206  Instruction: CALL 0x206
+/// I: 0x0000  Stack depth: 16
+/// V0: 00  V1: 00  V2: 00  V3: 00
+/// ...
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+  pub kind: FaultKind,
+  /// Address of the faulting instruction.
+  pub pc: usize,
+  /// Raw instruction in pc, unless pc is outside the memory.
+  pub opcode: Option<u16>,
+  /// The instruction decoded, unless the fault is decoding it.
+  pub instruction: Option<Instruction>,
+  pub registers: [u8; REG_SIZE],
+  pub reg_i: usize,
+  pub stack_depth: usize,
+}
+
+impl Fault {
+  /// Capture the state of the emulator for the fault of the instruction in pc.
+  pub fn new(kind: FaultKind, emu: &Emulator, pc: usize, instruction: Option<Instruction>) -> Self {
+    Self {
+      kind,
+      pc,
+      opcode: emu.opcode_at(pc),
+      instruction,
+      registers: *emu.registers(),
+      reg_i: emu.reg_i(),
+      stack_depth: emu.stack().depth(),
+    }
+  }
+}
+
+impl fmt::Display for Fault {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "ferret-8 crash report")?;
+    writeln!(f, "Error: {}", self.kind)?;
+    write!(f, "PC: 0x{:04X}", self.pc)?;
+    if let Some(opcode) = self.opcode {
+      write!(f, "  Opcode: 0x{:04X}", opcode)?;
+    }
+    if let Some(instruction) = self.instruction {
+      write!(f, "  Instruction: {}", instruction)?;
+    }
+    writeln!(f)?;
+    writeln!(f, "I: 0x{:04X}  Stack depth: {}", self.reg_i, self.stack_depth)?;
+    for (n, row) in self.registers.chunks(4).enumerate() {
+      let regs: Vec<String> =
+        row.iter().enumerate().map(|(m, v)| format!("V{:X}: {:02X}", n * 4 + m, v)).collect();
+      writeln!(f, "{}", regs.join("  "))?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for Fault {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.kind)
+  }
+}
+
+/// Kinds of faults, each one with its own action in the FaultPolicy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultClass {
+  /// A word that is not an instruction, or a 0nnn call without native routine.
+  UnknownOpcode,
+  /// An access beyond the memory (also a font digit out of the font).
+  InvalidAddress,
+  /// A stack overflow or underflow.
+  Stack,
+  /// A key value beyond F.
+  UnknownKey,
+}
+
+impl FaultClass {
+  pub const ALL: [Self; 4] =
+    [Self::UnknownOpcode, Self::InvalidAddress, Self::Stack, Self::UnknownKey];
+
+  /// Name in the fault rules.
+  pub fn name(&self) -> &'static str {
+    match self {
+      | Self::UnknownOpcode => "unknown-opcode",
+      | Self::InvalidAddress => "invalid-address",
+      | Self::Stack => "stack",
+      | Self::UnknownKey => "unknown-key",
+    }
+  }
+}
+
+impl FromStr for FaultClass {
+  type Err = PolicyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .into_iter()
+      .find(|class| class.name() == s.to_ascii_lowercase())
+      .ok_or_else(|| PolicyError::UnknownClass(s.to_string()))
+  }
+}
+
+impl FaultKind {
+  pub fn class(&self) -> FaultClass {
+    match self {
+      | Self::Decode(_) | Self::Execute(EmuError::UnknownRoutine(_)) => FaultClass::UnknownOpcode,
+      | Self::Execute(EmuError::UnknownKey(_)) => FaultClass::UnknownKey,
+      | Self::Execute(EmuError::Stack(_)) => FaultClass::Stack,
+      | Self::Execute(_) => FaultClass::InvalidAddress,
+    }
+  }
+}
+
+/// What to do when the program faults.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaultAction {
+  /// Stop the program with the crash report.
+  #[default]
+  Halt,
+  /// Report the fault and pause, to inspect the program in the debugger. Without window, halt.
+  Pause,
+  /// Log the fault and skip the rest of the instruction.
+  Nop,
+  /// Never fault: addresses wrap around the memory, the stack pointer around the stack and keys
+  /// (and font digits) are taken modulo 16.
+  Wrap,
+}
+
+impl FaultAction {
+  /// Name in the fault rules.
+  pub fn name(&self) -> &'static str {
+    match self {
+      | Self::Halt => "halt",
+      | Self::Pause => "pause",
+      | Self::Nop => "nop",
+      | Self::Wrap => "wrap",
+    }
+  }
+}
+
+impl FromStr for FaultAction {
+  type Err = PolicyError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      | "halt" => Ok(Self::Halt),
+      | "pause" => Ok(Self::Pause),
+      | "nop" => Ok(Self::Nop),
+      | "wrap" => Ok(Self::Wrap),
+      | _ => Err(PolicyError::UnknownAction(s.to_string())),
+    }
+  }
+}
+
+/// An action for a fault class, or for all of them, like "stack=wrap" or "all=nop".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FaultRule {
+  /// None for all the classes.
+  class: Option<FaultClass>,
+  action: FaultAction,
+}
+
+impl FaultRule {
+  pub fn parse(s: &str) -> Result<Self, PolicyError> {
+    let (class, action) =
+      s.split_once('=').ok_or_else(|| PolicyError::InvalidRule(s.to_string()))?;
+    let class = match class.trim() {
+      | "all" => None,
+      | class => Some(class.parse()?),
+    };
+    let action: FaultAction = action.trim().parse()?;
+    if action == FaultAction::Wrap && class.is_none_or(|class| class == FaultClass::UnknownOpcode) {
+      return Err(PolicyError::CannotWrap(FaultClass::UnknownOpcode.name().to_string()));
+    }
+    Ok(Self { class, action })
+  }
+}
+
+/// Action for each fault class. By default, every fault halts the program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultPolicy {
+  actions: [FaultAction; 4],
+}
+
+impl FaultPolicy {
+  /// Halt on every fault.
+  pub const HALT: Self = Self { actions: [FaultAction::Halt; 4] };
+
+  pub fn action(&self, class: FaultClass) -> FaultAction {
+    self.actions[class as usize]
+  }
+
+  /// Set the action of a class. Unknown opcodes can not wrap.
+  pub fn set(&mut self, class: FaultClass, action: FaultAction) -> Result<(), PolicyError> {
+    if class == FaultClass::UnknownOpcode && action == FaultAction::Wrap {
+      return Err(PolicyError::CannotWrap(class.name().to_string()));
+    }
+    self.actions[class as usize] = action;
+    Ok(())
+  }
+
+  /// Set the action of the rule, on top of the previous ones.
+  pub fn apply(&mut self, rule: &FaultRule) {
+    match rule.class {
+      | Some(class) => self.actions[class as usize] = rule.action,
+      | None => self.actions.fill(rule.action),
+    }
+  }
+
+  /// Return true if the faults of the class wrap around instead.
+  pub fn wraps(&self, class: FaultClass) -> bool {
+    self.action(class) == FaultAction::Wrap
+  }
+
+  /// Return true if any class pauses.
+  pub fn pauses(&self) -> bool {
+    self.actions.contains(&FaultAction::Pause)
+  }
+
+  /// Action for a fault that happened anyway: Halt, Pause or Nop. Fetching beyond the memory
+  /// can not be skipped, so it halts instead.
+  pub fn resolve(&self, fault: &Fault) -> FaultAction {
+    match self.action(fault.kind.class()) {
+      | FaultAction::Nop if fault.opcode.is_some() => FaultAction::Nop,
+      | FaultAction::Pause => FaultAction::Pause,
+      | _ => FaultAction::Halt,
+    }
+  }
+}
+
+/// The rule of every class, separated by commas, like
+/// "unknown-opcode=halt,invalid-address=wrap,stack=halt,unknown-key=halt".
+impl fmt::Display for FaultPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (n, class) in FaultClass::ALL.into_iter().enumerate() {
+      let separator = if n == 0 { "" } else { "," };
+      write!(f, "{}{}={}", separator, class.name(), self.action(class).name())?;
+    }
+    Ok(())
+  }
+}
+
+/// Faults ignored with Nop and already logged. A faulting instruction usually runs again and
+/// again (in a loop, each frame), so each one is logged only the first time.
+#[derive(Clone, Debug, Default)]
+pub struct FaultLog {
+  seen: HashSet<(FaultClass, usize)>,
+}
+
+impl FaultLog {
+  /// Whether the fault is the first of its class in its address, to log it.
+  pub fn first(&mut self, fault: &Fault) -> bool {
+    self.seen.insert((fault.kind.class(), fault.pc))
+  }
+}
+
+/// Fetch and decode the next instruction (through the cache of the emulator).
+pub fn fetch_decode(emu: &mut Emulator) -> Result<Instruction, Fault> {
+  let pc = emu.reg_pc();
+  emu.fetch_decoded().map_err(|kind| Fault::new(kind, emu, pc, None))
+}
+
+/// Execute the instruction just fetched by fetch_decode from the address pc (fetching already
+/// moved the program counter, and wrapped it at the end of the memory).
+pub fn execute<R: Rng + ?Sized>(
+  emu: &mut Emulator, pc: usize, instr: Instruction, rng: &mut R, keys: &[bool],
+) -> Result<(), Fault> {
+  emu.execute(instr, rng, keys).map_err(|err| Fault::new(err.into(), emu, pc, Some(instr)))
+}
+
+/// Fetch, decode and execute the next instruction, returning it.
+///
+/// On error, the Fault records the state of the emulator where it happened.
+pub fn step<R: Rng + ?Sized>(
+  emu: &mut Emulator, rng: &mut R, keys: &[bool],
+) -> Result<Instruction, Fault> {
+  let pc = emu.reg_pc();
+  let instr = fetch_decode(emu)?;
+  execute(emu, pc, instr, rng, keys)?;
+  Ok(instr)
+}
+
+#[cfg(test)]
+mod test {
+  use crate::decoder::Instruction;
+  use crate::decoder::error::DecodeError;
+  use crate::emulator::error::EmuError;
+  use crate::emulator::{Emulator, MEMORY_SIZE, Quirks, StackError};
+  use crate::fault::error::{FaultKind, PolicyError};
+  use crate::fault::{Fault, FaultAction, FaultClass, FaultLog, FaultPolicy, FaultRule, step};
+
+  #[test]
+  fn test_step_faults() {
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    // CALL 0x202 calling itself until the stack is full.
+    let mut emu = Emulator::new(Quirks::default());
+    emu.load_program(&[0x60, 0x2A, 0x22, 0x02]).unwrap();
+    assert_eq!(step(&mut emu, &mut rng, &keys), Ok(Instruction::LoadInmm(0x0, 0x2A)));
+    let fault = (0..20).find_map(|_| step(&mut emu, &mut rng, &keys).err()).unwrap();
+    assert_eq!(fault.kind, FaultKind::Execute(EmuError::Stack(StackError::Overflow)));
+    assert_eq!(fault.pc, 0x202);
+    assert_eq!(fault.opcode, Some(0x2202));
+    assert_eq!(fault.instruction, Some(Instruction::Call(0x202)));
+    assert_eq!(fault.registers[0], 0x2A);
+    assert_eq!(fault.stack_depth, 16);
+    let report = fault.to_string();
+    assert!(report.contains("Error: Stack overflow"));
+    assert!(report.contains("PC: 0x0202  Opcode: 0x2202  Instruction: CALL 0x202"));
+    assert!(report.contains("V0: 2A  V1: 00  V2: 00  V3: 00"));
+
+    // Data that is not an instruction.
+    let mut emu = Emulator::new(Quirks::default());
+    emu.load_program(&[0xFF, 0xFF]).unwrap();
+    let fault = step(&mut emu, &mut rng, &keys).unwrap_err();
+    assert_eq!(fault.kind, FaultKind::Decode(DecodeError::Unknown(0xFFFF)));
+    assert_eq!(fault.instruction, None);
+    assert!(fault.to_string().contains("PC: 0x0200  Opcode: 0xFFFF\n"));
+
+    // The last instruction of the memory, with the fetch wrapping the program counter to 0.
+    let mut emu = Emulator::new(Quirks::XO_CHIP);
+    let mut policy = FaultPolicy::default();
+    policy.set(FaultClass::InvalidAddress, FaultAction::Wrap).unwrap();
+    emu.set_fault_policy(policy);
+    emu.memory_mut()[MEMORY_SIZE - 2..].copy_from_slice(&[0xE0, 0x9E]);
+    emu.registers_mut()[0x0] = 0x15;
+    emu.set_reg_pc(MEMORY_SIZE - 2);
+    let fault = step(&mut emu, &mut rng, &keys).unwrap_err();
+    assert_eq!(fault.kind, FaultKind::Execute(EmuError::UnknownKey(0x15)));
+    assert_eq!(fault.pc, MEMORY_SIZE - 2);
+  }
+
+  #[test]
+  fn test_policy() {
+    let mut policy = FaultPolicy::default();
+    for rule in ["all=nop", "stack=wrap", "unknown-key = pause"] {
+      policy.apply(&FaultRule::parse(rule).unwrap());
+    }
+    assert_eq!(policy.action(FaultClass::UnknownOpcode), FaultAction::Nop);
+    assert_eq!(policy.action(FaultClass::InvalidAddress), FaultAction::Nop);
+    assert!(policy.wraps(FaultClass::Stack));
+    assert!(policy.pauses());
+
+    assert_eq!(FaultRule::parse("stack"), Err(PolicyError::InvalidRule("stack".to_string())));
+    assert_eq!(FaultRule::parse("heap=nop"), Err(PolicyError::UnknownClass("heap".to_string())));
+    assert_eq!(FaultRule::parse("stack=skip"), Err(PolicyError::UnknownAction("skip".to_string())));
+    let cannot_wrap = PolicyError::CannotWrap("unknown-opcode".to_string());
+    assert_eq!(FaultRule::parse("unknown-opcode=wrap"), Err(cannot_wrap.clone()));
+    assert_eq!(FaultRule::parse("all=wrap"), Err(cannot_wrap.clone()));
+    assert_eq!(policy.set(FaultClass::UnknownOpcode, FaultAction::Wrap), Err(cannot_wrap));
+  }
+
+  #[test]
+  fn test_resolve() {
+    let mut rng = rand::rng();
+    let keys = [false; 16];
+    let mut policy = FaultPolicy::default();
+    policy.set(FaultClass::UnknownOpcode, FaultAction::Nop).unwrap();
+    let mut emu = Emulator::new(Quirks::default());
+    emu.load_program(&[0xFF, 0xFF, 0x60, 0x01]).unwrap();
+    let fault = step(&mut emu, &mut rng, &keys).unwrap_err();
+    assert_eq!(policy.resolve(&fault), FaultAction::Nop);
+    // The next instruction runs after the ignored one.
+    assert_eq!(step(&mut emu, &mut rng, &keys), Ok(Instruction::LoadInmm(0x0, 0x01)));
+    // Fetching beyond the memory can not be skipped.
+    let fault = Fault { opcode: None, ..fault };
+    assert_eq!(policy.resolve(&fault), FaultAction::Halt);
+
+    let mut log = FaultLog::default();
+    assert!(log.first(&fault));
+    assert!(!log.first(&fault));
+    assert!(log.first(&Fault { pc: fault.pc + 2, ..fault }));
+  }
+}
@@ -0,0 +1,61 @@
+//! error.rs
+//! Errors the running program can fault with.
+
+use std::{error::Error, fmt};
+
+use crate::decoder::error::DecodeError;
+use crate::emulator::error::EmuError;
+
+/// Error that stopped the program, either decoding or executing an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+  Decode(DecodeError),
+  Execute(EmuError),
+}
+
+impl fmt::Display for FaultKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::Decode(err) => write!(f, "{}", err),
+      | Self::Execute(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+impl Error for FaultKind {}
+
+impl From<DecodeError> for FaultKind {
+  fn from(err: DecodeError) -> Self {
+    Self::Decode(err)
+  }
+}
+
+impl From<EmuError> for FaultKind {
+  fn from(err: EmuError) -> Self {
+    Self::Execute(err)
+  }
+}
+
+/// Possible errors parsing the fault policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+  UnknownClass(String),
+  UnknownAction(String),
+  /// A rule that is not CLASS=ACTION.
+  InvalidRule(String),
+  /// Wrap for a class that can not wrap around.
+  CannotWrap(String),
+}
+
+impl fmt::Display for PolicyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::UnknownClass(s) => write!(f, "Unknown fault class: {}", s),
+      | Self::UnknownAction(s) => write!(f, "Unknown fault action: {}", s),
+      | Self::InvalidRule(s) => write!(f, "Invalid fault rule (expected CLASS=ACTION): {}", s),
+      | Self::CannotWrap(s) => write!(f, "The faults of {} can not wrap around", s),
+    }
+  }
+}
+
+impl Error for PolicyError {}
@@ -1,21 +1,44 @@
 //! frontend.rs
 //! Manage to interconect the GUI and the emulator backend.
 
-use crate::UPSCALE_FACTOR;
-use crate::emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH, Emulator};
+use crate::audio::Beeper;
+use crate::emulator::{
+  AUDIO_PATTERN_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH, Emulator, TIMER_FREQUENCY,
+};
+use crate::keymap::{GAMEPADS, Keymap, PAD_KEYS};
 
 use raylib::prelude::*;
 
 /// Target fps of the frontend.
-pub const TARGET_FPS: u32 = 60;
+pub const TARGET_FPS: u32 = TIMER_FREQUENCY;
 
-const PIXEL_COLOR: Color = Color::new(255, 223, 194, 255);
-const BG_COLOR: Color = Color::new(0, 0, 0, 255);
+/// Sample rate of the audio stream.
+pub const SAMPLE_RATE: u32 = 44100;
+/// Samples per audio buffer update (about 23ms at 44100Hz).
+const AUDIO_BUFFER_SIZE: usize = 1024;
+
+/// Width of the debugger panel, at the right of the display.
+const DEBUG_PANEL_WIDTH: usize = 260;
+const DEBUG_FONT_SIZE: i32 = 18;
+const DEBUG_TEXT_COLOR: Color = Color::new(255, 223, 194, 255);
+const DEBUG_BG_COLOR: Color = Color::new(30, 30, 30, 255);
+
+/// Color for each pixel value. Index 0 is the background, index 1 the first plane (the only one
+/// outside XO-CHIP), index 2 the second plane, and index 3 both planes.
+const PALETTE: [Color; 4] = [
+  Color::new(0, 0, 0, 255),
+  Color::new(255, 223, 194, 255),
+  Color::new(186, 98, 70, 255),
+  Color::new(94, 44, 38, 255),
+];
 
 /// Return an initalized tuple (RaylibHandle, RaylibThread), setting some basic options.
-pub fn init_raylib(title: &str) -> (RaylibHandle, RaylibThread) {
-  let upscale_factor = UPSCALE_FACTOR.read().unwrap().clone();
-  let size_w = (DISPLAY_WIDTH * upscale_factor) as i32;
+/// With debug, the window is wider to make room for the debugger panel.
+pub fn init_raylib(
+  title: &str, upscale_factor: usize, debug: bool,
+) -> (RaylibHandle, RaylibThread) {
+  let panel_w = if debug { DEBUG_PANEL_WIDTH } else { 0 };
+  let size_w = (DISPLAY_WIDTH * upscale_factor + panel_w) as i32;
   let size_h = (DISPLAY_HEIGHT * upscale_factor) as i32;
   let (mut rl, thread) =
     raylib::init().size(size_w, size_h).title(&format!("Ferret-8: {}", title)).build();
@@ -24,84 +47,309 @@ pub fn init_raylib(title: &str) -> (RaylibHandle, RaylibThread) {
   (rl, thread)
 }
 
-/// Print a single pixel in the position (x, y)
-fn print_pixel(d: &mut RaylibDrawHandle, x: usize, y: usize) {
-  let upscale_factor = UPSCALE_FACTOR.read().unwrap().clone();
-  let x_pos = (x * upscale_factor) as i32;
-  let y_pos = (y * upscale_factor) as i32;
-  let size = upscale_factor as i32;
-  d.draw_rectangle(x_pos, y_pos, size, size, PIXEL_COLOR);
+/// Initialize the audio device, with the stream buffer size used by the Speaker.
+pub fn init_audio() -> Result<RaylibAudio, RaylibAudioInitError> {
+  let audio = RaylibAudio::init_audio_device()?;
+  audio.set_audio_stream_buffer_size_default(AUDIO_BUFFER_SIZE as i32);
+  Ok(audio)
 }
 
-/// Draw the current state of the emulator.
-pub fn draw_display(d: &mut RaylibDrawHandle, emu: &Emulator) {
-  d.clear_background(BG_COLOR);
-  for y in 0..DISPLAY_HEIGHT {
-    for x in 0..DISPLAY_WIDTH {
-      if emu.display_val(x, y) {
-        print_pixel(d, x, y);
-      }
-    }
-  }
+/// Plays the samples of a Beeper through a raylib audio stream.
+pub struct Speaker<'aud> {
+  stream: AudioStream<'aud>,
+  beeper: Beeper,
+  samples: Vec<f32>,
+  raw: Vec<u8>,
 }
 
-/// Return an array of bools for true/false for the CHIP-8 keypad.
-///
-/// KEYPAD     KEYBOARD\
-/// 1 2 3 C -> 1 2 3 4\
-/// 4 5 6 D -> q w e r\
-/// 7 8 9 E -> a s d f\
-/// A 0 B F -> z x c v
-pub fn get_input(rl: &mut RaylibHandle) -> [bool; 16] {
-  let mut keys = [false; 16];
-  if rl.is_key_down(KeyboardKey::KEY_ONE) {
-    keys[0x1] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_TWO) {
-    keys[0x2] = true;
+impl<'aud> Speaker<'aud> {
+  /// Creates a new speaker and starts playing the stream (silence until the beeper is active).
+  ///
+  /// The stream uses 8 bit mono samples: raylib's update takes the buffer length in bytes
+  /// as the number of frames, which only matches with 1 byte frames.
+  pub fn new(audio: &'aud RaylibAudio, beeper: Beeper) -> Self {
+    let stream = audio.new_audio_stream(beeper.sample_rate(), 8, 1);
+    stream.play();
+    Self { stream, beeper, samples: vec![0.0; AUDIO_BUFFER_SIZE], raw: vec![0; AUDIO_BUFFER_SIZE] }
   }
-  if rl.is_key_down(KeyboardKey::KEY_THREE) {
-    keys[0x3] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_FOUR) {
-    keys[0xC] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_Q) {
-    keys[0x4] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_W) {
-    keys[0x5] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_E) {
-    keys[0x6] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_R) {
-    keys[0xD] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_A) {
-    keys[0x7] = true;
-  }
-  if rl.is_key_down(KeyboardKey::KEY_S) {
-    keys[0x8] = true;
+
+  /// Play the XO-CHIP audio pattern with the pitch instead of the tone, or the tone with None.
+  pub fn set_pattern(&mut self, pattern: Option<&[u8; AUDIO_PATTERN_SIZE]>, pitch: u8) {
+    self.beeper.set_pattern(pattern, pitch);
   }
-  if rl.is_key_down(KeyboardKey::KEY_D) {
-    keys[0x9] = true;
+
+  /// Feed the stream with a new buffer if it has consumed the previous one.
+  pub fn update(&mut self, active: bool) {
+    if !self.stream.is_processed() {
+      return;
+    }
+    self.beeper.render(active, &mut self.samples);
+    for (raw, sample) in self.raw.iter_mut().zip(self.samples.iter()) {
+      *raw = (128.0 + sample * 127.0) as u8;
+    }
+    self.stream.update(&self.raw);
   }
-  if rl.is_key_down(KeyboardKey::KEY_F) {
-    keys[0xE] = true;
+}
+
+/// Print a single pixel in the position (x, y), scaled to the display area of the window
+/// (scale_w times the display width, scale_h times its height, as fractions).
+///
+/// Each pixel goes from its scaled position to the one of the next pixel, so the pixels fill
+/// the whole area even if they can't all be the same size (an odd factor in high resolution).
+fn print_pixel(
+  d: &mut RaylibDrawHandle, x: usize, y: usize, scale_w: (usize, usize), scale_h: (usize, usize),
+  color: Color,
+) {
+  let pos = |n: usize, (num, den): (usize, usize)| (n * num / den) as i32;
+  let (left, right) = (pos(x, scale_w), pos(x + 1, scale_w));
+  let (top, bottom) = (pos(y, scale_h), pos(y + 1, scale_h));
+  d.draw_rectangle(left, top, right - left, bottom - top, color);
+}
+
+/// Draw the current state of the emulator.
+///
+/// The window keeps the low resolution size, so in high resolution each pixel is half the size
+/// (with an upscale factor of 1, only one of each two pixels fits).
+pub fn draw_display(d: &mut RaylibDrawHandle, emu: &Emulator, upscale_factor: usize) {
+  d.clear_background(PALETTE[0]);
+  let scale_w = (DISPLAY_WIDTH * upscale_factor, emu.display_width());
+  let scale_h = (DISPLAY_HEIGHT * upscale_factor, emu.display_height());
+  for y in 0..emu.display_height() {
+    for x in 0..emu.display_width() {
+      let value = emu.display_val(x, y) as usize;
+      if value != 0 {
+        print_pixel(d, x, y, scale_w, scale_h, PALETTE[value]);
+      }
+    }
   }
-  if rl.is_key_down(KeyboardKey::KEY_Z) {
-    keys[0xA] = true;
+}
+
+/// Draw the debugger panel at the right of the display, one line per string.
+pub fn draw_debugger(d: &mut RaylibDrawHandle, lines: &[String], upscale_factor: usize) {
+  let x = (DISPLAY_WIDTH * upscale_factor) as i32;
+  let h = (DISPLAY_HEIGHT * upscale_factor) as i32;
+  d.draw_rectangle(x, 0, DEBUG_PANEL_WIDTH as i32, h, DEBUG_BG_COLOR);
+  for (n, line) in lines.iter().enumerate() {
+    let y = 4 + n as i32 * (DEBUG_FONT_SIZE + 2);
+    d.draw_text(line, x + 8, y, DEBUG_FONT_SIZE, DEBUG_TEXT_COLOR);
   }
-  if rl.is_key_down(KeyboardKey::KEY_X) {
-    keys[0x0] = true;
+}
+
+/// Debugger hotkeys pressed in the current frame.
+///
+/// F5: pause or continue.\
+/// F10: execute a single instruction (while paused).\
+/// F9: toggle a breakpoint in the current program counter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DebugInput {
+  pub toggle_pause: bool,
+  pub step: bool,
+  pub toggle_breakpoint: bool,
+}
+
+/// Return the debugger hotkeys pressed in the current frame.
+pub fn get_debug_input(rl: &RaylibHandle) -> DebugInput {
+  DebugInput {
+    toggle_pause: rl.is_key_pressed(KeyboardKey::KEY_F5),
+    step: rl.is_key_pressed_repeat(KeyboardKey::KEY_F10) || rl.is_key_pressed(KeyboardKey::KEY_F10),
+    toggle_breakpoint: rl.is_key_pressed(KeyboardKey::KEY_F9),
   }
-  if rl.is_key_down(KeyboardKey::KEY_C) {
-    keys[0xB] = true;
+}
+
+/// Number of quick-save slots, one per function key from F1.
+pub const STATE_SLOTS: usize = 4;
+const SLOT_KEYS: [KeyboardKey; STATE_SLOTS] =
+  [KeyboardKey::KEY_F1, KeyboardKey::KEY_F2, KeyboardKey::KEY_F3, KeyboardKey::KEY_F4];
+
+/// Save state hotkey pressed in the current frame, with its slot.
+///
+/// Shift + F1..F4: save the state in the slot.\
+/// F1..F4: load the state from the slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateInput {
+  Save(usize),
+  Load(usize),
+}
+
+/// Return the save state hotkey pressed in the current frame, if any.
+pub fn get_state_input(rl: &RaylibHandle) -> Option<StateInput> {
+  let shift =
+    rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+  let slot = SLOT_KEYS.iter().position(|key| rl.is_key_pressed(*key))?;
+  Some(if shift { StateInput::Save(slot) } else { StateInput::Load(slot) })
+}
+
+/// Return true while the rewind key (Backspace) is held down.
+pub fn is_rewinding(rl: &RaylibHandle) -> bool {
+  rl.is_key_down(KeyboardKey::KEY_BACKSPACE)
+}
+
+/// Keyboard keys and gamepad buttons bound to each CHIP-8 key, resolved from a Keymap.
+pub struct InputMap {
+  keys: [Vec<KeyboardKey>; PAD_KEYS],
+  buttons: [[Vec<GamepadButton>; PAD_KEYS]; GAMEPADS],
+}
+
+impl InputMap {
+  pub fn new(keymap: &Keymap) -> Self {
+    Self {
+      keys: std::array::from_fn(|n| {
+        keymap.bindings(n).iter().filter_map(|name| keyboard_key(name)).collect()
+      }),
+      buttons: std::array::from_fn(|pad| {
+        std::array::from_fn(|n| {
+          keymap.buttons(pad, n).iter().filter_map(|name| gamepad_button(name)).collect()
+        })
+      }),
+    }
   }
-  if rl.is_key_down(KeyboardKey::KEY_V) {
-    keys[0xF] = true;
+}
+
+impl Default for InputMap {
+  fn default() -> Self {
+    Self::new(&Keymap::default())
   }
+}
+
+/// Raylib key for each name in keymap::KEY_NAMES.
+fn keyboard_key(name: &str) -> Option<KeyboardKey> {
+  use KeyboardKey::*;
+  let key = match name {
+    | "0" => KEY_ZERO,
+    | "1" => KEY_ONE,
+    | "2" => KEY_TWO,
+    | "3" => KEY_THREE,
+    | "4" => KEY_FOUR,
+    | "5" => KEY_FIVE,
+    | "6" => KEY_SIX,
+    | "7" => KEY_SEVEN,
+    | "8" => KEY_EIGHT,
+    | "9" => KEY_NINE,
+    | "A" => KEY_A,
+    | "B" => KEY_B,
+    | "C" => KEY_C,
+    | "D" => KEY_D,
+    | "E" => KEY_E,
+    | "F" => KEY_F,
+    | "G" => KEY_G,
+    | "H" => KEY_H,
+    | "I" => KEY_I,
+    | "J" => KEY_J,
+    | "K" => KEY_K,
+    | "L" => KEY_L,
+    | "M" => KEY_M,
+    | "N" => KEY_N,
+    | "O" => KEY_O,
+    | "P" => KEY_P,
+    | "Q" => KEY_Q,
+    | "R" => KEY_R,
+    | "S" => KEY_S,
+    | "T" => KEY_T,
+    | "U" => KEY_U,
+    | "V" => KEY_V,
+    | "W" => KEY_W,
+    | "X" => KEY_X,
+    | "Y" => KEY_Y,
+    | "Z" => KEY_Z,
+    | "Kp0" => KEY_KP_0,
+    | "Kp1" => KEY_KP_1,
+    | "Kp2" => KEY_KP_2,
+    | "Kp3" => KEY_KP_3,
+    | "Kp4" => KEY_KP_4,
+    | "Kp5" => KEY_KP_5,
+    | "Kp6" => KEY_KP_6,
+    | "Kp7" => KEY_KP_7,
+    | "Kp8" => KEY_KP_8,
+    | "Kp9" => KEY_KP_9,
+    | "KpDecimal" => KEY_KP_DECIMAL,
+    | "KpDivide" => KEY_KP_DIVIDE,
+    | "KpMultiply" => KEY_KP_MULTIPLY,
+    | "KpSubtract" => KEY_KP_SUBTRACT,
+    | "KpAdd" => KEY_KP_ADD,
+    | "KpEnter" => KEY_KP_ENTER,
+    | "Up" => KEY_UP,
+    | "Down" => KEY_DOWN,
+    | "Left" => KEY_LEFT,
+    | "Right" => KEY_RIGHT,
+    | "Space" => KEY_SPACE,
+    | "Enter" => KEY_ENTER,
+    | "Tab" => KEY_TAB,
+    | "Apostrophe" => KEY_APOSTROPHE,
+    | "Comma" => KEY_COMMA,
+    | "Minus" => KEY_MINUS,
+    | "Period" => KEY_PERIOD,
+    | "Slash" => KEY_SLASH,
+    | "Semicolon" => KEY_SEMICOLON,
+    | "Equal" => KEY_EQUAL,
+    | "LeftBracket" => KEY_LEFT_BRACKET,
+    | "Backslash" => KEY_BACKSLASH,
+    | "RightBracket" => KEY_RIGHT_BRACKET,
+    | "Grave" => KEY_GRAVE,
+    | "LeftShift" => KEY_LEFT_SHIFT,
+    | "LeftControl" => KEY_LEFT_CONTROL,
+    | "LeftAlt" => KEY_LEFT_ALT,
+    | "RightShift" => KEY_RIGHT_SHIFT,
+    | "RightControl" => KEY_RIGHT_CONTROL,
+    | "RightAlt" => KEY_RIGHT_ALT,
+    | _ => return None,
+  };
+  Some(key)
+}
+
+/// Raylib button for each name in keymap::BUTTON_NAMES.
+fn gamepad_button(name: &str) -> Option<GamepadButton> {
+  use GamepadButton::*;
+  let button = match name {
+    | "DpadUp" => GAMEPAD_BUTTON_LEFT_FACE_UP,
+    | "DpadDown" => GAMEPAD_BUTTON_LEFT_FACE_DOWN,
+    | "DpadLeft" => GAMEPAD_BUTTON_LEFT_FACE_LEFT,
+    | "DpadRight" => GAMEPAD_BUTTON_LEFT_FACE_RIGHT,
+    | "A" => GAMEPAD_BUTTON_RIGHT_FACE_DOWN,
+    | "B" => GAMEPAD_BUTTON_RIGHT_FACE_RIGHT,
+    | "X" => GAMEPAD_BUTTON_RIGHT_FACE_LEFT,
+    | "Y" => GAMEPAD_BUTTON_RIGHT_FACE_UP,
+    | "LB" => GAMEPAD_BUTTON_LEFT_TRIGGER_1,
+    | "RB" => GAMEPAD_BUTTON_RIGHT_TRIGGER_1,
+    | "LT" => GAMEPAD_BUTTON_LEFT_TRIGGER_2,
+    | "RT" => GAMEPAD_BUTTON_RIGHT_TRIGGER_2,
+    | "Select" => GAMEPAD_BUTTON_MIDDLE_LEFT,
+    | "Start" => GAMEPAD_BUTTON_MIDDLE_RIGHT,
+    | "LeftThumb" => GAMEPAD_BUTTON_LEFT_THUMB,
+    | "RightThumb" => GAMEPAD_BUTTON_RIGHT_THUMB,
+    | _ => return None,
+  };
+  Some(button)
+}
 
+/// Return an array of bools for true/false for the CHIP-8 keypad: a key is held if any of its
+/// bound keyboard keys, or buttons of the connected gamepads, is down.
+pub fn get_input(rl: &mut RaylibHandle, input: &InputMap) -> [bool; PAD_KEYS] {
+  let mut keys: [bool; PAD_KEYS] =
+    std::array::from_fn(|n| input.keys[n].iter().any(|key| rl.is_key_down(*key)));
+  for (pad, buttons) in input.buttons.iter().enumerate() {
+    if !rl.is_gamepad_available(pad as i32) {
+      continue;
+    }
+    for (key, buttons) in keys.iter_mut().zip(buttons) {
+      *key |= buttons.iter().any(|button| rl.is_gamepad_button_down(pad as i32, *button));
+    }
+  }
   keys
 }
+
+#[cfg(test)]
+mod test {
+  use crate::frontend::{gamepad_button, keyboard_key};
+  use crate::keymap::{BUTTON_NAMES, KEY_NAMES};
+
+  /// InputMap drops the names without a raylib key, so all of them must have one.
+  #[test]
+  fn test_names() {
+    for name in KEY_NAMES {
+      assert!(keyboard_key(name).is_some(), "no raylib key for {}", name);
+    }
+    for name in BUTTON_NAMES {
+      assert!(gamepad_button(name).is_some(), "no raylib button for {}", name);
+    }
+  }
+}
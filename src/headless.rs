@@ -0,0 +1,245 @@
+//! headless.rs
+//! Run programs without a window, scripting the keys and dumping the final state.
+
+pub mod error;
+
+use crate::emulator::Emulator;
+use crate::engine::Executor;
+use crate::fault::{FaultAction, FaultLog};
+use crate::headless::error::ScriptError;
+use crate::scheduler::Scheduler;
+
+use anyhow::Result;
+use rand::prelude::*;
+
+/// Source of the keys held during each frame.
+pub trait KeySource {
+  fn keys(&self, frame: u64) -> [bool; 16];
+}
+
+/// Keys held during each frame, read from a script.
+///
+/// Each line is a frame number followed by the keys (hexadecimal, 0 to F) held from that frame
+/// onwards, until the next line. A frame without keys releases all of them. Text after a # is
+/// ignored. Example:
+/// ```text
+/// # Press 5 during the frames 60 to 89, then 4 and 6 together.
+/// 60 5
+/// 90 4 6
+/// 120
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyScript {
+  /// Frame where each change happens and the keys held from it, ordered by frame.
+  changes: Vec<(u64, [bool; 16])>,
+}
+
+impl KeyScript {
+  pub fn parse(text: &str) -> Result<Self, ScriptError> {
+    let mut changes: Vec<(u64, [bool; 16])> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+      let line_number = n + 1;
+      let line = line.split('#').next().unwrap_or_default();
+      let mut words = line.split_whitespace();
+      let Some(frame) = words.next() else {
+        continue;
+      };
+      let frame: u64 = frame.parse().map_err(|_| ScriptError::InvalidFrame(line_number))?;
+      if changes.last().is_some_and(|(last, _)| *last > frame) {
+        return Err(ScriptError::Unordered(line_number));
+      }
+      let mut keys = [false; 16];
+      for word in words {
+        let key = u8::from_str_radix(word, 16)
+          .ok()
+          .filter(|key| *key < 16)
+          .ok_or_else(|| ScriptError::InvalidKey(line_number, word.to_string()))?;
+        keys[key as usize] = true;
+      }
+      changes.push((frame, keys));
+    }
+    Ok(Self { changes })
+  }
+}
+
+impl KeySource for KeyScript {
+  /// Return the keys held in the frame.
+  fn keys(&self, frame: u64) -> [bool; 16] {
+    let index = self.changes.partition_point(|(start, _)| *start <= frame);
+    if index == 0 { [false; 16] } else { self.changes[index - 1].1 }
+  }
+}
+
+/// Run the emulator for the number of frames (one per timer tick), with the keys of the source.
+///
+/// Each frame decreases the timers and then executes, with the engine of the executor, the
+/// instructions the scheduler gives to the tick, like the window does. Stops early if the
+/// program exits. Return the number of frames run, the last one included.
+///
+/// The faults are handled with the policy of the emulator: Nop faults are logged (once per class
+/// and address), and the rest halt (there is no debugger to pause in).
+pub fn run_frames<R: Rng + ?Sized>(
+  emu: &mut Emulator, rng: &mut R, frames: u64, mut scheduler: Scheduler, mut executor: Executor,
+  input: &dyn KeySource,
+) -> Result<u64> {
+  let mut log = FaultLog::default();
+  if emu.has_exited() {
+    return Ok(0);
+  }
+  for frame in 0..frames {
+    let keys = input.keys(frame);
+    emu.decrease_timers();
+    scheduler.start_tick();
+    while scheduler.has_budget() {
+      if let Err(fault) = executor.step(emu, rng, &keys, &mut scheduler) {
+        match emu.fault_policy().resolve(&fault) {
+          | FaultAction::Nop => {
+            if log.first(&fault) {
+              eprintln!("Ignored fault at 0x{:04X}: {}", fault.pc, fault.kind);
+            }
+          },
+          | _ => return Err(fault.into()),
+        }
+      }
+      // Checked after each instruction, not to start another frame after the last one.
+      if emu.has_exited() {
+        return Ok(frame + 1);
+      }
+    }
+  }
+  Ok(frames)
+}
+
+/// Character for each pixel value of the display.
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '%'];
+/// Gray level for each pixel value of the display, in the PNG dumps.
+const GRAY_PIXELS: [u8; 4] = [0, 255, 170, 85];
+
+/// The display as text, one line per row: . is off, # the first plane, + the second plane and
+/// % both.
+pub fn display_ascii(emu: &Emulator) -> String {
+  let mut out = String::with_capacity((emu.display_width() + 1) * emu.display_height());
+  for y in 0..emu.display_height() {
+    for x in 0..emu.display_width() {
+      out.push(ASCII_PIXELS[emu.display_val(x, y) as usize]);
+    }
+    out.push('\n');
+  }
+  out
+}
+
+/// The display as a plain PBM image, with any lit plane as black.
+pub fn display_pbm(emu: &Emulator) -> String {
+  let mut out = format!("P1\n{} {}\n", emu.display_width(), emu.display_height());
+  for y in 0..emu.display_height() {
+    let row: Vec<&str> = (0..emu.display_width())
+      .map(|x| if emu.display_val(x, y) != 0 { "1" } else { "0" })
+      .collect();
+    out.push_str(&row.join(" "));
+    out.push('\n');
+  }
+  out
+}
+
+/// The display as a grayscale PNG image: black is off, white the first plane, and gray the
+/// second plane (light) or both (dark).
+pub fn display_png(emu: &Emulator) -> Result<Vec<u8>, png::EncodingError> {
+  let (width, height) = (emu.display_width(), emu.display_height());
+  let mut data = Vec::with_capacity(width * height);
+  for y in 0..height {
+    for x in 0..width {
+      data.push(GRAY_PIXELS[emu.display_val(x, y) as usize]);
+    }
+  }
+  let mut out = Vec::new();
+  let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+  encoder.set_color(png::ColorType::Grayscale);
+  encoder.set_depth(png::BitDepth::Eight);
+  let mut writer = encoder.write_header()?;
+  writer.write_image_data(&data)?;
+  writer.finish()?;
+  Ok(out)
+}
+
+/// The registers, timers and stack as a JSON object.
+pub fn registers_json(emu: &Emulator) -> String {
+  let join = |values: Vec<String>| values.join(", ");
+  let v = join(emu.registers().iter().map(|v| v.to_string()).collect());
+  let stack = join(emu.stack().as_slice().iter().map(|v| v.to_string()).collect());
+  format!(
+    "{{\n  \"v\": [{}],\n  \"i\": {},\n  \"pc\": {},\n  \"delay\": {},\n  \"sound\": {},\n  \
+     \"stack\": [{}],\n  \"exited\": {}\n}}\n",
+    v,
+    emu.reg_i(),
+    emu.reg_pc(),
+    emu.delay_timer(),
+    emu.sound_timer(),
+    stack,
+    emu.has_exited()
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use crate::emulator::{Emulator, Quirks};
+  use crate::headless::error::ScriptError;
+  use crate::headless::*;
+  use crate::timing::Timing;
+
+  #[test]
+  fn test_key_script() {
+    let script = KeyScript::parse("# comment\n10 5\n\n20 4 f # both\n30\n").unwrap();
+    assert_eq!(script.keys(0), [false; 16]);
+    assert!(script.keys(10)[5]);
+    assert!(script.keys(19)[5]);
+    assert!(!script.keys(20)[5]);
+    assert!(script.keys(25)[4] && script.keys(25)[0xF]);
+    assert_eq!(script.keys(1000), [false; 16]);
+
+    assert_eq!(KeyScript::parse("a 5"), Err(ScriptError::InvalidFrame(1)));
+    assert_eq!(KeyScript::parse("1 10"), Err(ScriptError::InvalidKey(1, "10".to_string())));
+    assert_eq!(KeyScript::parse("5\n1 2"), Err(ScriptError::Unordered(2)));
+  }
+
+  #[test]
+  fn test_run_frames() {
+    // Loop until the key 5 is pressed, then draw its font sprite at (0, 0) and loop.
+    let program = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x0A];
+    let mut emu = Emulator::new(Quirks::COSMAC_VIP);
+    emu.load_program(&program).unwrap();
+    let script = KeyScript::parse("2 5\n4").unwrap();
+    let scheduler = Scheduler::new(600, Timing::Flat);
+    let run = run_frames(&mut emu, &mut rand::rng(), 10, scheduler, Executor::default(), &script);
+    assert_eq!(run.unwrap(), 10);
+
+    let ascii = display_ascii(&emu);
+    assert_eq!(&ascii[..5], "####.");
+    assert_eq!(ascii.lines().count(), 32);
+    assert!(display_pbm(&emu).starts_with("P1\n64 32\n1 1 1 1 0 "));
+    assert!(display_png(&emu).unwrap().starts_with(b"\x89PNG"));
+    let json = registers_json(&emu);
+    assert!(json.contains("\"v\": [5, 0,"));
+    assert!(json.contains("\"pc\": 522"));
+
+    // Waits for a frame and exits.
+    let mut emu = Emulator::new(Quirks::SCHIP);
+    emu
+      .load_program(&[0x60, 0x01, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x04, 0x00, 0xFD])
+      .unwrap();
+    let scheduler = Scheduler::new(600, Timing::Flat);
+    let run = run_frames(&mut emu, &mut rand::rng(), 10, scheduler, Executor::default(), &script);
+    assert_eq!(run.unwrap(), 2);
+
+    // Exits with the last instruction of the third frame, one per frame: the timers don't tick
+    // again.
+    let mut emu = Emulator::new(Quirks::SCHIP);
+    emu.load_program(&[0x60, 0x05, 0xF0, 0x15, 0x00, 0xFD]).unwrap();
+    let scheduler = Scheduler::new(60, Timing::Flat);
+    let run = run_frames(&mut emu, &mut rand::rng(), 10, scheduler, Executor::default(), &script);
+    assert_eq!(run.unwrap(), 3);
+    assert_eq!(emu.delay_timer(), 4);
+    let scheduler = Scheduler::new(60, Timing::Flat);
+    let run = run_frames(&mut emu, &mut rand::rng(), 10, scheduler, Executor::default(), &script);
+    assert_eq!(run.unwrap(), 0);
+  }
+}
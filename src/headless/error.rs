@@ -0,0 +1,26 @@
+//! error.rs
+//! Errors parsing a key script.
+
+use std::{error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptError {
+  /// The line (1-based) does not start with a frame number.
+  InvalidFrame(usize),
+  /// The line (1-based) contains something that is not a key from 0 to F.
+  InvalidKey(usize, String),
+  /// The line (1-based) has a frame lower than the previous line.
+  Unordered(usize),
+}
+
+impl fmt::Display for ScriptError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::InvalidFrame(line) => write!(f, "Line {}: expected a frame number", line),
+      | Self::InvalidKey(line, s) => write!(f, "Line {}: invalid key: {}", line, s),
+      | Self::Unordered(line) => write!(f, "Line {}: frame lower than the previous line", line),
+    }
+  }
+}
+
+impl Error for ScriptError {}
@@ -0,0 +1,402 @@
+//! keymap.rs
+//! Keyboard layouts, gamepad buttons and the key configuration file.
+
+pub mod error;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::keymap::error::ConfigError;
+
+use serde::Deserialize;
+
+/// Number of keys in the CHIP-8 pad.
+pub const PAD_KEYS: usize = 16;
+
+/// Names of the keyboard keys that can be bound. Function keys and Backspace are left out, they
+/// are the frontend hotkeys.
+///
+/// The names are physical positions, named after the key in a US QWERTY keyboard, whatever the
+/// layout of the system: "Q" is the first letter of the top row, also in an AZERTY keyboard
+/// (where it types an A).
+#[rustfmt::skip]
+pub const KEY_NAMES: &[&str] = &[
+  "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+  "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+  "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+  "Kp0", "Kp1", "Kp2", "Kp3", "Kp4", "Kp5", "Kp6", "Kp7", "Kp8", "Kp9",
+  "KpDecimal", "KpDivide", "KpMultiply", "KpSubtract", "KpAdd", "KpEnter",
+  "Up", "Down", "Left", "Right", "Space", "Enter", "Tab",
+  "Apostrophe", "Comma", "Minus", "Period", "Slash", "Semicolon", "Equal",
+  "LeftBracket", "Backslash", "RightBracket", "Grave",
+  "LeftShift", "LeftControl", "LeftAlt", "RightShift", "RightControl", "RightAlt",
+];
+
+/// Gamepads that can be bound, numbered from 1 in the config file.
+pub const GAMEPADS: usize = 4;
+
+/// Names of the gamepad buttons that can be bound. The face buttons are named by their position,
+/// like in an Xbox pad: A down, B right, X left and Y up. LB/RB are the shoulders and LT/RT the
+/// triggers.
+#[rustfmt::skip]
+pub const BUTTON_NAMES: &[&str] = &[
+  "DpadUp", "DpadDown", "DpadLeft", "DpadRight", "A", "B", "X", "Y",
+  "LB", "RB", "LT", "RT", "Select", "Start", "LeftThumb", "RightThumb",
+];
+
+/// Default buttons of the first gamepad: the D-pad as 5/8/7/9 (up, down, left, right) and the
+/// face buttons A/B as 6/4, the keys most games use.
+const DEFAULT_GAMEPAD: [(usize, &str); 6] = [
+  (0x5, "DpadUp"),
+  (0x8, "DpadDown"),
+  (0x7, "DpadLeft"),
+  (0x9, "DpadRight"),
+  (0x6, "A"),
+  (0x4, "B"),
+];
+
+/// Built-in keymaps. All of them but the numpad use the 4x4 block at the left of the keyboard,
+/// from the number row down, like the COSMAC VIP keypad. The keys are positions (see KEY_NAMES),
+/// so it is the same block of keys in every layout, typing:
+/// ```text
+/// KEYPAD     QWERTY    AZERTY    QWERTZ    DVORAK
+/// 1 2 3 C    1 2 3 4   1 2 3 4   1 2 3 4   1 2 3 4
+/// 4 5 6 D    q w e r   a z e r   q w e r   ' , . p
+/// 7 8 9 E    a s d f   q s d f   a s d f   a o e u
+/// A 0 B F    z x c v   w x c v   y x c v   ; q j k
+/// ```
+/// AZERTY, QWERTZ and Dvorak are aliases of QWERTY, to name the layout of the keyboard.
+///
+/// The numpad binds each digit to its own key, and A to F to / * - + Enter and the decimal point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+  #[default]
+  Qwerty,
+  Azerty,
+  Qwertz,
+  Dvorak,
+  Numpad,
+}
+
+impl Layout {
+  /// Keyboard key bound to each CHIP-8 key, from 0 to F.
+  fn keys(&self) -> [&'static str; PAD_KEYS] {
+    match self {
+      | Self::Qwerty | Self::Azerty | Self::Qwertz | Self::Dvorak => {
+        ["X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V"]
+      },
+      | Self::Numpad => [
+        "Kp0",
+        "Kp1",
+        "Kp2",
+        "Kp3",
+        "Kp4",
+        "Kp5",
+        "Kp6",
+        "Kp7",
+        "Kp8",
+        "Kp9",
+        "KpDivide",
+        "KpMultiply",
+        "KpSubtract",
+        "KpAdd",
+        "KpEnter",
+        "KpDecimal",
+      ],
+    }
+  }
+}
+
+impl FromStr for Layout {
+  type Err = ConfigError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      | "qwerty" => Ok(Self::Qwerty),
+      | "azerty" => Ok(Self::Azerty),
+      | "qwertz" => Ok(Self::Qwertz),
+      | "dvorak" => Ok(Self::Dvorak),
+      | "numpad" => Ok(Self::Numpad),
+      | _ => Err(ConfigError::UnknownLayout(s.to_string())),
+    }
+  }
+}
+
+/// Keyboard keys (names from KEY_NAMES) and gamepad buttons (names from BUTTON_NAMES) bound to
+/// each CHIP-8 key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+  bindings: [Vec<&'static str>; PAD_KEYS],
+  buttons: [[Vec<&'static str>; PAD_KEYS]; GAMEPADS],
+}
+
+impl Keymap {
+  /// Creates the keymap of the layout, with the default buttons in the first gamepad.
+  pub fn new(layout: Layout) -> Self {
+    let mut buttons: [[Vec<&'static str>; PAD_KEYS]; GAMEPADS] = Default::default();
+    for (key, button) in DEFAULT_GAMEPAD {
+      buttons[0][key].push(button);
+    }
+    Self { bindings: layout.keys().map(|name| vec![name]), buttons }
+  }
+
+  /// Keyboard keys bound to the CHIP-8 key.
+  pub fn bindings(&self, key: usize) -> &[&'static str] {
+    &self.bindings[key]
+  }
+
+  /// Replace the keyboard keys bound to the CHIP-8 key. The names are case insensitive.
+  pub fn bind(&mut self, key: usize, names: &[String]) -> Result<(), ConfigError> {
+    self.bindings[key] = names.iter().map(|name| key_name(name)).collect::<Result<_, _>>()?;
+    Ok(())
+  }
+
+  /// Buttons of the gamepad (from 0) bound to the CHIP-8 key.
+  pub fn buttons(&self, gamepad: usize, key: usize) -> &[&'static str] {
+    &self.buttons[gamepad][key]
+  }
+
+  /// Replace the buttons of the gamepad (from 0) bound to the CHIP-8 key.
+  /// The names are case insensitive.
+  pub fn bind_buttons(
+    &mut self, gamepad: usize, key: usize, names: &[String],
+  ) -> Result<(), ConfigError> {
+    self.buttons[gamepad][key] =
+      names.iter().map(|name| button_name(name)).collect::<Result<_, _>>()?;
+    Ok(())
+  }
+}
+
+impl Default for Keymap {
+  fn default() -> Self {
+    Self::new(Layout::default())
+  }
+}
+
+/// Return the canonical name of a keyboard key, ignoring the case.
+fn key_name(name: &str) -> Result<&'static str, ConfigError> {
+  KEY_NAMES
+    .iter()
+    .find(|known| known.eq_ignore_ascii_case(name))
+    .copied()
+    .ok_or_else(|| ConfigError::UnknownKey(name.to_string()))
+}
+
+/// Return the canonical name of a gamepad button, ignoring the case.
+fn button_name(name: &str) -> Result<&'static str, ConfigError> {
+  BUTTON_NAMES
+    .iter()
+    .find(|known| known.eq_ignore_ascii_case(name))
+    .copied()
+    .ok_or_else(|| ConfigError::UnknownButton(name.to_string()))
+}
+
+/// Parse a gamepad number, from 1 to GAMEPADS, into an index.
+fn gamepad(s: &str) -> Result<usize, ConfigError> {
+  s.parse::<usize>()
+    .ok()
+    .filter(|n| (1..=GAMEPADS).contains(n))
+    .map(|n| n - 1)
+    .ok_or_else(|| ConfigError::InvalidGamepad(s.to_string()))
+}
+
+/// Parse a CHIP-8 key, a single hexadecimal digit.
+fn pad_key(s: &str) -> Result<usize, ConfigError> {
+  usize::from_str_radix(s, 16)
+    .ok()
+    .filter(|key| s.len() == 1 && *key < PAD_KEYS)
+    .ok_or_else(|| ConfigError::InvalidPadKey(s.to_string()))
+}
+
+/// A layout and the bindings replaced over it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Bindings {
+  layout: Option<String>,
+  #[serde(default)]
+  keys: BTreeMap<String, Vec<String>>,
+  /// Buttons for each CHIP-8 key, for each gamepad.
+  #[serde(default)]
+  gamepads: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+/// Contents of the config file:
+/// ```toml
+/// layout = "azerty"
+///
+/// # Replace the keys bound to some CHIP-8 keys, for every program.
+/// [keys]
+/// 0 = ["X", "Space"]
+///
+/// # Same for the buttons of each gamepad, from 1.
+/// [gamepads.1]
+/// 6 = ["A", "RB"]
+///
+/// # Only for a program, by its file name.
+/// [rom."pong.ch8"]
+/// keys = { 1 = ["W", "Up"], 4 = ["S", "Down"] }
+/// gamepads.2 = { C = ["DpadUp"], D = ["DpadDown"] }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+  #[serde(flatten)]
+  global: Bindings,
+  #[serde(default)]
+  rom: BTreeMap<String, Bindings>,
+}
+
+impl Config {
+  /// Parse and check the config file.
+  pub fn parse(text: &str) -> Result<Self, ConfigError> {
+    let config: Self =
+      toml::from_str(text).map_err(|e| ConfigError::Parse(e.message().to_string()))?;
+    // Fail now, instead of when the program is run.
+    for bindings in std::iter::once(&config.global).chain(config.rom.values()) {
+      let mut keymap = Keymap::default();
+      bindings.apply(&mut keymap)?;
+    }
+    Ok(config)
+  }
+
+  /// Keymap for a program, by its file name. The layout (if some) replaces the one in the file.
+  ///
+  /// The layout is the first of: the argument, the one for the program or the global one.
+  /// Then the global keys are bound, and the ones for the program last.
+  pub fn keymap(&self, rom_name: &str, layout: Option<Layout>) -> Result<Keymap, ConfigError> {
+    let rom = self.rom.get(rom_name);
+    let layout = match layout {
+      | Some(layout) => layout,
+      | None => match rom.and_then(|r| r.layout.as_deref()).or(self.global.layout.as_deref()) {
+        | Some(name) => name.parse()?,
+        | None => Layout::default(),
+      },
+    };
+    let mut keymap = Keymap::new(layout);
+    self.global.apply(&mut keymap)?;
+    if let Some(rom) = rom {
+      rom.apply(&mut keymap)?;
+    }
+    Ok(keymap)
+  }
+}
+
+impl Bindings {
+  /// Bind the keys and buttons over the keymap. The layout is checked, but applied by
+  /// Config::keymap.
+  fn apply(&self, keymap: &mut Keymap) -> Result<(), ConfigError> {
+    if let Some(layout) = &self.layout {
+      layout.parse::<Layout>()?;
+    }
+    for (key, names) in &self.keys {
+      keymap.bind(pad_key(key)?, names)?;
+    }
+    for (number, buttons) in &self.gamepads {
+      let gamepad = gamepad(number)?;
+      for (key, names) in buttons {
+        keymap.bind_buttons(gamepad, pad_key(key)?, names)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::keymap::error::ConfigError;
+  use crate::keymap::{Config, KEY_NAMES, Keymap, Layout, PAD_KEYS};
+
+  #[test]
+  fn test_layouts() {
+    for layout in [Layout::Qwerty, Layout::Azerty, Layout::Qwertz, Layout::Dvorak, Layout::Numpad] {
+      let keymap = Keymap::new(layout);
+      let mut names: Vec<_> = (0..PAD_KEYS).map(|key| keymap.bindings(key)[0]).collect();
+      assert!(names.iter().all(|name| KEY_NAMES.contains(name)));
+      names.sort();
+      names.dedup();
+      assert_eq!(names.len(), PAD_KEYS);
+    }
+    assert_eq!(Keymap::new(Layout::Qwerty).bindings(0x4), ["Q"]);
+    // The keys are positions: the key typing A in AZERTY is the Q of QWERTY.
+    assert_eq!(Keymap::new(Layout::Azerty), Keymap::new(Layout::Qwerty));
+    assert_eq!(Keymap::new(Layout::Dvorak).bindings(0x4), ["Q"]);
+    assert_eq!("QWERTZ".parse::<Layout>(), Ok(Layout::Qwertz));
+    assert_eq!("NumPad".parse::<Layout>(), Ok(Layout::Numpad));
+    assert_eq!("colemak".parse::<Layout>(), Err(ConfigError::UnknownLayout("colemak".into())));
+  }
+
+  #[test]
+  fn test_config() {
+    let text = r#"
+      layout = "azerty"
+      [keys]
+      0 = ["x", "Space"]
+      [rom."pong.ch8"]
+      layout = "numpad"
+      keys = { 1 = ["Up"], c = ["Down"] }
+    "#;
+    let config = Config::parse(text).unwrap();
+
+    let keymap = config.keymap("other.ch8", None).unwrap();
+    assert_eq!(keymap.bindings(0x0), ["X", "Space"]);
+    assert_eq!(keymap.bindings(0x4), ["Q"]);
+
+    let keymap = config.keymap("pong.ch8", None).unwrap();
+    assert_eq!(keymap.bindings(0x0), ["X", "Space"]);
+    assert_eq!(keymap.bindings(0x1), ["Up"]);
+    assert_eq!(keymap.bindings(0xC), ["Down"]);
+    assert_eq!(keymap.bindings(0x4), ["Kp4"]);
+
+    let keymap = config.keymap("pong.ch8", Some(Layout::Qwerty)).unwrap();
+    assert_eq!(keymap.bindings(0x4), ["Q"]);
+  }
+
+  #[test]
+  fn test_gamepads() {
+    let keymap = Keymap::default();
+    assert_eq!(keymap.buttons(0, 0x5), ["DpadUp"]);
+    assert!(keymap.buttons(1, 0x5).is_empty());
+
+    let text = r#"
+      [gamepads.1]
+      5 = []
+      6 = ["a", "RB"]
+      [rom."pong.ch8".gamepads.2]
+      C = ["DpadUp"]
+    "#;
+    let config = Config::parse(text).unwrap();
+    let keymap = config.keymap("pong.ch8", None).unwrap();
+    assert!(keymap.buttons(0, 0x5).is_empty());
+    assert_eq!(keymap.buttons(0, 0x6), ["A", "RB"]);
+    assert_eq!(keymap.buttons(0, 0x8), ["DpadDown"]);
+    assert_eq!(keymap.buttons(1, 0xC), ["DpadUp"]);
+    assert!(config.keymap("other.ch8", None).unwrap().buttons(1, 0xC).is_empty());
+
+    assert_eq!(
+      Config::parse("[gamepads.1]\n1 = [\"Z\"]").unwrap_err(),
+      ConfigError::UnknownButton("Z".into())
+    );
+    assert_eq!(
+      Config::parse("[gamepads.5]\n1 = [\"A\"]").unwrap_err(),
+      ConfigError::InvalidGamepad("5".into())
+    );
+  }
+
+  #[test]
+  fn test_config_errors() {
+    assert!(matches!(Config::parse("layout = 1"), Err(ConfigError::Parse(_))));
+    assert!(matches!(Config::parse("other = 1"), Err(ConfigError::Parse(_))));
+    assert_eq!(
+      Config::parse("layout = \"foo\"").unwrap_err(),
+      ConfigError::UnknownLayout("foo".into())
+    );
+    assert_eq!(
+      Config::parse("[keys]\n1 = [\"F5\"]").unwrap_err(),
+      ConfigError::UnknownKey("F5".into())
+    );
+    assert_eq!(
+      Config::parse("[rom.a]\nkeys = { 10 = [\"A\"] }").unwrap_err(),
+      ConfigError::InvalidPadKey("10".into())
+    );
+  }
+}
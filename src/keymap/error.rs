@@ -0,0 +1,36 @@
+//! error.rs
+//! Possible errors loading the key configuration.
+
+use std::{error::Error, fmt};
+
+use crate::keymap::GAMEPADS;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+  /// The file is not valid TOML, or does not have the expected fields.
+  Parse(String),
+  UnknownLayout(String),
+  /// A keyboard key name that is not supported.
+  UnknownKey(String),
+  /// A CHIP-8 key that is not a single hexadecimal digit.
+  InvalidPadKey(String),
+  /// A gamepad button name that is not supported.
+  UnknownButton(String),
+  /// A gamepad number out of range.
+  InvalidGamepad(String),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::Parse(s) => write!(f, "Invalid config file: {}", s),
+      | Self::UnknownLayout(s) => write!(f, "Unknown keyboard layout: {}", s),
+      | Self::UnknownKey(s) => write!(f, "Unknown keyboard key: {}", s),
+      | Self::InvalidPadKey(s) => write!(f, "Invalid CHIP-8 key (0 to F): {}", s),
+      | Self::UnknownButton(s) => write!(f, "Unknown gamepad button: {}", s),
+      | Self::InvalidGamepad(s) => write!(f, "Invalid gamepad number (1 to {}): {}", GAMEPADS, s),
+    }
+  }
+}
+
+impl Error for ConfigError {}
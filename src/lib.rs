@@ -0,0 +1,25 @@
+//! lib.rs
+//! Core of the CHIP-8 interpreter, usable without any frontend.
+//!
+//! The raylib frontend is only built with the `frontend` feature (enabled by default).
+
+pub mod assembler;
+pub mod audio;
+pub mod debugger;
+pub mod decoder;
+pub mod disasm;
+pub mod emulator;
+pub mod engine;
+pub mod fault;
+#[cfg(feature = "frontend")]
+pub mod frontend;
+pub mod headless;
+pub mod keymap;
+pub mod movie;
+pub mod rewind;
+pub mod rng;
+pub mod scheduler;
+pub mod timing;
+
+pub use self::decoder::{Instruction, decode, error::DecodeError};
+pub use self::emulator::{Emulator, Quirks, StackError, StateError, error::EmuError};
@@ -1,68 +1,429 @@
 //! main.rs
 //! Entry point of the binary.
 
-use std::{fs, io::Read};
+use std::io::{self, Read, Write};
+use std::{fs, path::Path};
 
 use crate::cli::*;
-use crate::decoder::decode;
-use crate::emulator::Emulator;
-use crate::frontend::TARGET_FPS;
 
 use anyhow::Result;
+use ferret_8::assembler::assemble;
+use ferret_8::disasm::disassemble;
+use ferret_8::emulator::savestate::error::StateError;
+use ferret_8::emulator::{Emulator, START_ADDR};
+use ferret_8::engine::{Engine, Executor};
+use ferret_8::fault::Fault;
+use ferret_8::headless::{self, KeyScript, KeySource};
+#[cfg(feature = "frontend")]
+use ferret_8::keymap::{Config, Keymap};
+use ferret_8::movie::Movie;
+use ferret_8::rng::SeededRng;
+use ferret_8::scheduler::Scheduler;
 
 mod cli;
-mod decoder;
-mod emulator;
-mod frontend;
+
+/// Frames to run in headless mode, unless set or playing a movie (10 seconds).
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+
+/// Input movie of the session, from --movie or --record.
+enum MovieMode {
+  Play(Movie),
+  Record(Movie),
+}
 
 fn main() -> Result<()> {
-  parse_arguments();
-
-  // Retrieve necesary variables from the cli arguments.
-  let program_name = PROGRAM_NAME.read().unwrap().clone();
-  let cycles_per_frame = {
-    let mut aux = CYCLES.read().unwrap().clone() / TARGET_FPS as usize;
-    if aux < 1 {
-      aux = 1;
-    }
-    aux
+  let result = match parse_arguments() {
+    | Some(command) => run_command(command),
+    | None => {
+      let program_name = PROGRAM_NAME.read().unwrap().clone();
+      load_emulator(&program_name)
+        .and_then(|(mut emu, mut rng, movie)| run(&program_name, &mut emu, &mut rng, movie))
+    },
+  };
+  if let Err(err) = &result {
+    save_crash_report(err);
+  }
+  result
+}
+
+/// If the program faulted, write its crash report to the file from the arguments, if any.
+/// A failed write is only warned about, the fault is the error to report.
+fn save_crash_report(err: &anyhow::Error) {
+  if let Some(path) = CRASH_REPORT.read().unwrap().as_deref()
+    && let Some(fault) = err.downcast_ref::<Fault>()
+    && let Err(write_err) = fs::write(path, fault.to_string())
+  {
+    eprintln!("Could not write the crash report to {}: {}", path, write_err);
+  }
+}
+
+/// Creates the emulator with the quirks from the cli arguments, and loads the program
+/// (and the save state, if any).
+/// Also creates the random generator, with the seed from the arguments or the save state.
+///
+/// With --movie, the quirks, the seed and the fault policy are the ones the movie was recorded
+/// with, and the movie is returned to play it back (see new_scheduler for the cycles and the
+/// timing). With --record, an empty movie is returned to record it.
+fn load_emulator(program_name: &str) -> Result<(Emulator, SeededRng, Option<MovieMode>)> {
+  let mut quirks = *QUIRKS.read().unwrap();
+  let mut policy = *FAULT_POLICY.read().unwrap();
+  let vec = read_program(program_name)?;
+  let mut rng = match *SEED.read().unwrap() {
+    | Some(seed) => SeededRng::new(seed),
+    | None => SeededRng::from_entropy(),
+  };
+
+  let mut movie = None;
+  if let Some(path) = MOVIE.read().unwrap().as_deref() {
+    let playback = Movie::parse(&fs::read_to_string(path)?)?;
+    playback.check(&vec)?;
+    quirks = playback.quirks();
+    rng = SeededRng::new(playback.seed());
+    policy = playback.fault_policy();
+    movie = Some(MovieMode::Play(playback));
+  } else if RECORD.read().unwrap().is_some() {
+    let (cycles, timing) = (*CYCLES.read().unwrap(), *TIMING.read().unwrap());
+    let recording = Movie::new(&vec, quirks, rng.seed(), cycles, timing, policy);
+    movie = Some(MovieMode::Record(recording));
+  }
+
+  let mut emu = Emulator::new(quirks);
+  emu.set_fault_policy(policy);
+  emu.load_program(&vec)?;
+  if let Some(path) = LOAD_STATE.read().unwrap().as_deref() {
+    restore_state(&mut emu, &fs::read(path)?, &mut rng)?;
+  }
+  Ok((emu, rng, movie))
+}
+
+/// Restore a save state, with the random generator saved in it. A state saved without it
+/// (e.g. with Emulator::save_state) is still restored, keeping the current generator.
+fn restore_state(emu: &mut Emulator, data: &[u8], rng: &mut SeededRng) -> Result<(), StateError> {
+  match emu.load_state_with_rng(data, rng) {
+    | Err(StateError::MissingRng) => {
+      eprintln!("The state has no random generator, keeping the current one");
+      emu.load_state(data)
+    },
+    | result => result,
+  }
+}
+
+/// Keymap for the program, from the config file and the layout in the arguments.
+#[cfg(feature = "frontend")]
+fn load_keymap(program_name: &str) -> Result<Keymap> {
+  let config = match CONFIG.read().unwrap().as_deref() {
+    | Some(path) => Config::parse(&fs::read_to_string(path)?)?,
+    | None => Config::default(),
   };
+  let rom_name = Path::new(program_name).file_name().unwrap_or_default().to_string_lossy();
+  Ok(config.keymap(&rom_name, *LAYOUT.read().unwrap())?)
+}
+
+/// Scheduler with the cycles and timing from the cli arguments, or the ones the movie was
+/// recorded with when playing it back.
+fn new_scheduler(movie: &Option<MovieMode>) -> Scheduler {
+  match movie {
+    | Some(MovieMode::Play(movie)) => Scheduler::new(movie.cycles(), movie.timing()),
+    | _ => Scheduler::new(*CYCLES.read().unwrap(), *TIMING.read().unwrap()),
+  }
+}
+
+/// Executor with the engine from the cli arguments. With the debugger, always the interpreter,
+/// to check the breakpoints before each instruction.
+fn new_executor(debugger: bool) -> Executor {
+  Executor::new(if debugger { Engine::Interp } else { *ENGINE.read().unwrap() })
+}
+
+/// Write the recorded movie, if recording.
+fn save_movie(movie: &Movie) -> Result<()> {
+  if let Some(path) = RECORD.read().unwrap().as_deref() {
+    fs::write(path, movie.to_string())?;
+  }
+  Ok(())
+}
 
-  // Open and reach file.
+/// Open and read the whole program file.
+fn read_program(path: &str) -> Result<Vec<u8>> {
   let mut vec = Vec::new();
-  let mut file = fs::File::open(&program_name)?;
+  let mut file = fs::File::open(path)?;
   file.read_to_end(&mut vec)?;
+  Ok(vec)
+}
 
-  // Creates and load the emulator.
-  let mut emu = Emulator::new();
-  emu.load_program(&vec)?;
+/// Execute a subcommand instead of running the program.
+fn run_command(command: Command) -> Result<()> {
+  match command {
+    | Command::Run { program, headless: false, .. } => {
+      let (mut emu, mut rng, movie) = load_emulator(&program)?;
+      run(&program, &mut emu, &mut rng, movie)?;
+    },
+    | Command::Run { program, headless: true, frames, keys, screen, format, registers } => {
+      let (mut emu, mut rng, movie) = load_emulator(&program)?;
+      let script = match keys {
+        | Some(path) => KeyScript::parse(&fs::read_to_string(path)?)?,
+        | None => KeyScript::default(),
+      };
+      let (input, frames): (&dyn KeySource, u64) = match &movie {
+        | Some(MovieMode::Play(movie)) => (movie, frames.unwrap_or(movie.len())),
+        | _ => (&script, frames.unwrap_or(DEFAULT_HEADLESS_FRAMES)),
+      };
+      let (scheduler, executor) = (new_scheduler(&movie), new_executor(false));
+      let run = headless::run_frames(&mut emu, &mut rng, frames, scheduler, executor, input)?;
+      // Recording a headless run turns the key script into a movie, up to the frame where the
+      // program exited.
+      if let Some(MovieMode::Record(mut movie)) = movie {
+        for frame in 0..run {
+          movie.record(frame, &script.keys(frame));
+        }
+        save_movie(&movie)?;
+      }
 
-  // Generates an rng, necessary for a instruction in the emulator.
-  let mut rng = rand::rng();
-
-  // Creates the window.
-  let (mut rl, th) = frontend::init_raylib(&program_name);
-  
-  println!("COMPAT: {}", COMPATIBILITY.read().unwrap());
-
-  while !rl.window_should_close() {
-    emu.decrease_timers();
-    let input = frontend::get_input(&mut rl);
-    for _ in 0..cycles_per_frame {
-      // Fetch
-      let raw_instr = emu.fetch()?;
-      // Decode
-      let instr = decode(raw_instr)?;
-      // Execute
-      emu.execute(instr, &mut rng, &input)?;
+      let dump = match format {
+        | ScreenFormat::Ascii => headless::display_ascii(&emu).into_bytes(),
+        | ScreenFormat::Pbm => headless::display_pbm(&emu).into_bytes(),
+        | ScreenFormat::Png => headless::display_png(&emu)?,
+      };
+      match screen {
+        | Some(path) => fs::write(path, dump)?,
+        | None => io::stdout().write_all(&dump)?,
+      }
+      let json = headless::registers_json(&emu);
+      match registers {
+        | Some(path) => fs::write(path, json)?,
+        | None => print!("{}", json),
+      }
+    },
+    | Command::Disasm { program } => {
+      let vec = read_program(&program)?;
+      for line in disassemble(&vec, START_ADDR) {
+        println!("{}", line);
+      }
+    },
+    | Command::Asm { source, output } => {
+      let text = fs::read_to_string(&source)?;
+      let binary = assemble(&text)?;
+      let output = output
+        .unwrap_or_else(|| Path::new(&source).with_extension("ch8").to_string_lossy().into_owned());
+      fs::write(&output, binary)?;
+    },
+  }
+  Ok(())
+}
+
+/// Open the window and run the main loop until it is closed.
+#[cfg(feature = "frontend")]
+fn run(
+  program_name: &str, emu: &mut Emulator, rng: &mut SeededRng, mut movie: Option<MovieMode>,
+) -> Result<()> {
+  use ferret_8::audio::Beeper;
+  use ferret_8::debugger::Debugger;
+  use ferret_8::emulator::TIMER_FREQUENCY;
+  use ferret_8::frontend::{self, InputMap, SAMPLE_RATE, Speaker, StateInput};
+  use ferret_8::rewind::Rewind;
+  use std::time::Instant;
+
+  let upscale_factor = *UPSCALE_FACTOR.read().unwrap();
+  let mut scheduler = new_scheduler(&movie);
+  let input_map = InputMap::new(&load_keymap(program_name)?);
+
+  // Creates the window, with the debugger panel if debugging or pausing on faults.
+  let debug = *DEBUG.read().unwrap();
+  let panel = debug || emu.fault_policy().pauses();
+  let (mut rl, th) = frontend::init_raylib(program_name, upscale_factor, panel);
+
+  // Creates the debugger, starting paused when debugging.
+  let mut debugger = panel.then(|| {
+    let mut debugger = Debugger::new();
+    for breakpoint in BREAKPOINTS.read().unwrap().iter() {
+      debugger.add_breakpoint(*breakpoint);
+    }
+    if !debug {
+      debugger.resume();
+    }
+    debugger
+  });
+  let mut executor = new_executor(panel);
+
+  // Creates the audio output, unless muted. Without an audio device, the program runs muted.
+  let audio = if *MUTE.read().unwrap() {
+    None
+  } else {
+    frontend::init_audio()
+      .inspect_err(|err| eprintln!("Could not open the audio device, running muted: {}", err))
+      .ok()
+  };
+  let mut speaker = audio.as_ref().map(|audio| {
+    let beeper = Beeper::new(
+      SAMPLE_RATE,
+      *TONE.read().unwrap(),
+      *WAVEFORM.read().unwrap(),
+      *VOLUME.read().unwrap(),
+    );
+    Speaker::new(audio, beeper)
+  });
+
+  // History of the last frames, to rewind.
+  let mut rewind = Rewind::new(*REWIND_SECONDS.read().unwrap() * TIMER_FREQUENCY as usize);
+
+  // Frames (timer ticks) run since the start, to play back or record the movie.
+  let mut frame: u64 = 0;
+  let mut fault_log = ferret_8::fault::FaultLog::default();
+  let mut last_time = Instant::now();
+
+  while !rl.window_should_close() && !emu.has_exited() {
+    let now = Instant::now();
+    let ticks = scheduler.advance(now - last_time);
+    last_time = now;
+
+    let live_input = frontend::get_input(&mut rl, &input_map);
+    let input = frame_input(&movie, frame, live_input);
+
+    // Quick save and load. A failed load is reported, but the program keeps running. Loading
+    // while recording a movie is ignored: the movie can't play back a jump to another state.
+    let recording = matches!(movie, Some(MovieMode::Record(_)));
+    match frontend::get_state_input(&rl) {
+      | Some(StateInput::Save(slot)) => {
+        let path = state_path(program_name, slot);
+        if let Err(err) = fs::write(&path, emu.save_state_with_rng(rng)) {
+          eprintln!("Could not save the state in {}: {}", path, err);
+        }
+      },
+      | Some(StateInput::Load(_)) if recording => {
+        eprintln!("Can not load a state while recording a movie");
+      },
+      | Some(StateInput::Load(slot)) => {
+        let path = state_path(program_name, slot);
+        match fs::read(&path) {
+          | Ok(data) => {
+            if let Err(err) = restore_state(emu, &data, rng) {
+              eprintln!("Could not load the state from {}: {}", path, err);
+            }
+          },
+          | Err(err) => eprintln!("Could not read the state from {}: {}", path, err),
+        }
+      },
+      | None => {},
+    }
+
+    if let Some(debugger) = debugger.as_mut() {
+      let debug_input = frontend::get_debug_input(&rl);
+      if debug_input.toggle_breakpoint {
+        debugger.toggle_address(emu.reg_pc());
+      }
+      if debug_input.toggle_pause {
+        if debugger.is_paused() {
+          debugger.resume();
+        } else {
+          debugger.pause();
+        }
+      }
+      if debug_input.step
+        && debugger.is_paused()
+        && let Err(fault) = debugger.step(emu, rng, &input)
+      {
+        handle_fault(fault, emu.fault_policy(), &mut fault_log, Some(debugger))?;
+      }
+    }
+    let paused = debugger.as_ref().is_some_and(|d| d.is_paused());
+    // While held, go back one frame each frame instead of running.
+    let rewinding = !paused && frontend::is_rewinding(&rl) && rewind.step_back(emu, rng)?;
+    if rewinding {
+      frame = frame.saturating_sub(1);
+    }
+
+    if paused || rewinding {
+      // The time while stopped is not emulated later.
+      scheduler.reset();
+    } else {
+      'ticks: for _ in 0..ticks {
+        let input = frame_input(&movie, frame, live_input);
+        emu.decrease_timers();
+        scheduler.start_tick();
+        while scheduler.has_budget() {
+          if emu.has_exited() {
+            break 'ticks;
+          }
+          if let Some(debugger) = debugger.as_mut()
+            && debugger.check(emu)
+          {
+            break 'ticks;
+          }
+          if let Err(fault) = executor.step(emu, rng, &input, &mut scheduler) {
+            handle_fault(fault, emu.fault_policy(), &mut fault_log, debugger.as_mut())?;
+          }
+        }
+        rewind.push(emu, rng);
+        if let Some(MovieMode::Record(movie)) = movie.as_mut() {
+          movie.record(frame, &input);
+        }
+        frame += 1;
+      }
+    }
+
+    if let Some(speaker) = speaker.as_mut() {
+      speaker.set_pattern(emu.audio_pattern(), emu.pitch());
+      speaker.update(emu.is_sound_active() && !paused && !rewinding);
     }
 
     let mut d = rl.begin_drawing(&th);
     if emu.should_refresh() {
-      frontend::draw_display(&mut d, &emu);
+      frontend::draw_display(&mut d, emu, upscale_factor);
       emu.refreshed();
     }
+    if let Some(debugger) = debugger.as_ref() {
+      frontend::draw_debugger(&mut d, &debugger.report(emu), upscale_factor);
+    }
   }
 
+  if let Some(MovieMode::Record(movie)) = movie {
+    save_movie(&movie)?;
+  }
   Ok(())
 }
+
+/// Handle a fault with the policy of the emulator: Nop logs it (the first time in the log), and
+/// Pause prints the report and pauses the debugger. Otherwise, the fault is returned to halt.
+#[cfg(feature = "frontend")]
+fn handle_fault(
+  fault: Fault, policy: ferret_8::fault::FaultPolicy, log: &mut ferret_8::fault::FaultLog,
+  debugger: Option<&mut ferret_8::debugger::Debugger>,
+) -> Result<()> {
+  use ferret_8::fault::FaultAction;
+
+  match (policy.resolve(&fault), debugger) {
+    | (FaultAction::Nop, _) => {
+      if log.first(&fault) {
+        eprintln!("Ignored fault at 0x{:04X}: {}", fault.pc, fault.kind);
+      }
+    },
+    | (FaultAction::Pause, Some(debugger)) => {
+      eprint!("{}", fault);
+      debugger.pause();
+    },
+    | _ => return Err(fault.into()),
+  }
+  Ok(())
+}
+
+/// Keys of the frame: the ones from the movie being played, or else the live ones.
+#[cfg(feature = "frontend")]
+fn frame_input(movie: &Option<MovieMode>, frame: u64, live: [bool; 16]) -> [bool; 16] {
+  match movie {
+    | Some(MovieMode::Play(movie)) if frame < movie.len() => movie.keys(frame),
+    | _ => live,
+  }
+}
+
+/// Path of a quick-save slot, next to the program.
+#[cfg(feature = "frontend")]
+fn state_path(program_name: &str, slot: usize) -> String {
+  format!("{}.state{}", program_name, slot + 1)
+}
+
+/// Without the frontend there is no window to run the program in.
+#[cfg(not(feature = "frontend"))]
+fn run(
+  _program_name: &str, _emu: &mut Emulator, _rng: &mut SeededRng, _movie: Option<MovieMode>,
+) -> Result<()> {
+  anyhow::bail!("ferret-8 was built without the `frontend` feature, no window available")
+}
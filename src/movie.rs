@@ -0,0 +1,277 @@
+//! movie.rs
+//! Record and play back the keys of each frame, to reproduce a whole session.
+
+pub mod error;
+
+use std::fmt;
+
+use crate::emulator::Quirks;
+use crate::fault::{FaultPolicy, FaultRule};
+use crate::headless::KeySource;
+use crate::movie::error::MovieError;
+use crate::timing::Timing;
+
+/// First line of every movie file.
+const HEADER: &str = "ferret-8 movie";
+/// Format version, increased each time the layout changes.
+const VERSION: u32 = 1;
+
+/// The keys held in each frame of a session, and everything else needed to repeat it: the
+/// program (its hash), the quirks, the seed of the random generator, the instructions per second
+/// and timing of the scheduler, and the fault policy.
+///
+/// The file is plain text: the header and version, one line per field, and then one line per
+/// frame with the keys as a 16 bit hexadecimal mask (bit n set if key n is held).
+/// ```text
+/// ferret-8 movie 1
+/// rom 3a5c0e8d1f2b4c6e
+/// quirks 2d
+/// seed 1234
+/// cycles 700
+/// timing flat
+/// faults unknown-opcode=halt,invalid-address=wrap,stack=halt,unknown-key=halt
+/// frames
+/// 0000
+/// 0020
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+  rom_hash: u64,
+  quirks: Quirks,
+  seed: u64,
+  cycles: usize,
+  timing: Timing,
+  policy: FaultPolicy,
+  frames: Vec<u16>,
+}
+
+impl Movie {
+  /// Creates an empty movie for the program.
+  pub fn new(
+    program: &[u8], quirks: Quirks, seed: u64, cycles: usize, timing: Timing, policy: FaultPolicy,
+  ) -> Self {
+    let rom_hash = rom_hash(program);
+    Self { rom_hash, quirks, seed, cycles, timing, policy, frames: Vec::new() }
+  }
+
+  pub fn parse(text: &str) -> Result<Self, MovieError> {
+    let mut lines = text.lines().enumerate();
+    let version = lines
+      .next()
+      .and_then(|(_, line)| line.strip_prefix(HEADER))
+      .and_then(|v| v.trim().parse::<u32>().ok())
+      .ok_or(MovieError::InvalidHeader)?;
+    if version != VERSION {
+      return Err(MovieError::UnsupportedVersion(version));
+    }
+
+    let (mut rom_hash, mut quirks, mut seed) = (None, None, None);
+    let (mut cycles, mut timing, mut policy) = (None, None, None);
+    for (n, line) in lines.by_ref() {
+      let invalid = || MovieError::InvalidLine(n + 1);
+      match line.split_once(' ') {
+        | Some(("rom", value)) => {
+          rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?)
+        },
+        | Some(("quirks", value)) => {
+          quirks = Some(Quirks::from_bits(u8::from_str_radix(value, 16).map_err(|_| invalid())?))
+        },
+        | Some(("seed", value)) => seed = Some(value.parse().map_err(|_| invalid())?),
+        | Some(("cycles", value)) => cycles = Some(value.parse().map_err(|_| invalid())?),
+        | Some(("timing", value)) => timing = Some(parse_timing(value).ok_or_else(invalid)?),
+        | Some(("faults", value)) => policy = Some(parse_policy(value).ok_or_else(invalid)?),
+        | None if line == "frames" => break,
+        | _ => return Err(invalid()),
+      }
+    }
+
+    let mut frames = Vec::new();
+    for (n, line) in lines {
+      frames.push(u16::from_str_radix(line, 16).map_err(|_| MovieError::InvalidLine(n + 1))?);
+    }
+    Ok(Self {
+      rom_hash: rom_hash.ok_or(MovieError::MissingField("rom"))?,
+      quirks: quirks.ok_or(MovieError::MissingField("quirks"))?,
+      seed: seed.ok_or(MovieError::MissingField("seed"))?,
+      cycles: cycles.ok_or(MovieError::MissingField("cycles"))?,
+      timing: timing.ok_or(MovieError::MissingField("timing"))?,
+      policy: policy.ok_or(MovieError::MissingField("faults"))?,
+      frames,
+    })
+  }
+
+  /// Return an error if the movie was not recorded with the program.
+  pub fn check(&self, program: &[u8]) -> Result<(), MovieError> {
+    let running = rom_hash(program);
+    if running != self.rom_hash {
+      return Err(MovieError::RomMismatch { movie: self.rom_hash, running });
+    }
+    Ok(())
+  }
+
+  /// Quirks the movie was recorded with.
+  pub fn quirks(&self) -> Quirks {
+    self.quirks
+  }
+
+  /// Seed of the random generator the movie was recorded with.
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// Instructions per second the movie was recorded with.
+  pub fn cycles(&self) -> usize {
+    self.cycles
+  }
+
+  /// Timing the movie was recorded with.
+  pub fn timing(&self) -> Timing {
+    self.timing
+  }
+
+  /// Fault policy the movie was recorded with.
+  pub fn fault_policy(&self) -> FaultPolicy {
+    self.policy
+  }
+
+  /// Number of recorded frames.
+  pub fn len(&self) -> u64 {
+    self.frames.len() as u64
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  /// Record the keys of the frame, dropping any frame recorded after it (after a rewind).
+  pub fn record(&mut self, frame: u64, keys: &[bool; 16]) {
+    self.frames.truncate(frame as usize);
+    let mask = keys.iter().enumerate().fold(0, |acc, (n, k)| acc | ((*k as u16) << n));
+    self.frames.push(mask);
+  }
+}
+
+impl KeySource for Movie {
+  /// Return the keys recorded in the frame. After the end, no key is held.
+  fn keys(&self, frame: u64) -> [bool; 16] {
+    let mask = self.frames.get(frame as usize).copied().unwrap_or(0);
+    std::array::from_fn(|n| mask & (1 << n) != 0)
+  }
+}
+
+impl fmt::Display for Movie {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{} {}", HEADER, VERSION)?;
+    writeln!(f, "rom {:016x}", self.rom_hash)?;
+    writeln!(f, "quirks {:02x}", self.quirks.bits())?;
+    writeln!(f, "seed {}", self.seed)?;
+    writeln!(f, "cycles {}", self.cycles)?;
+    writeln!(f, "timing {}", timing_name(self.timing))?;
+    writeln!(f, "faults {}", self.policy)?;
+    writeln!(f, "frames")?;
+    for mask in &self.frames {
+      writeln!(f, "{:04x}", mask)?;
+    }
+    Ok(())
+  }
+}
+
+/// Name of the timing in the movie file.
+fn timing_name(timing: Timing) -> &'static str {
+  match timing {
+    | Timing::Flat => "flat",
+    | Timing::Vip => "vip",
+  }
+}
+
+fn parse_timing(name: &str) -> Option<Timing> {
+  match name {
+    | "flat" => Some(Timing::Flat),
+    | "vip" => Some(Timing::Vip),
+    | _ => None,
+  }
+}
+
+/// Parse the fault rules written by the Display of FaultPolicy.
+fn parse_policy(rules: &str) -> Option<FaultPolicy> {
+  let mut policy = FaultPolicy::default();
+  for rule in rules.split(',') {
+    policy.apply(&FaultRule::parse(rule).ok()?);
+  }
+  Some(policy)
+}
+
+/// 64 bit FNV-1a hash of the program, to identify it.
+pub fn rom_hash(program: &[u8]) -> u64 {
+  program.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use crate::emulator::Quirks;
+  use crate::fault::{FaultAction, FaultClass, FaultPolicy};
+  use crate::headless::KeySource;
+  use crate::movie::error::MovieError;
+  use crate::movie::{Movie, rom_hash};
+  use crate::timing::Timing;
+
+  #[test]
+  fn test_roundtrip() {
+    let program = [0x12, 0x00];
+    let mut policy = FaultPolicy::default();
+    policy.set(FaultClass::Stack, FaultAction::Wrap).unwrap();
+    let mut movie = Movie::new(&program, Quirks::SCHIP, 99, 1000, Timing::Vip, policy);
+    let mut keys = [false; 16];
+    movie.record(0, &keys);
+    keys[5] = true;
+    keys[0xF] = true;
+    movie.record(1, &keys);
+
+    let text = movie.to_string();
+    assert!(text.ends_with("frames\n0000\n8020\n"));
+    let parsed = Movie::parse(&text).unwrap();
+    assert_eq!(parsed, movie);
+    assert_eq!(parsed.quirks(), Quirks::SCHIP);
+    assert_eq!(parsed.seed(), 99);
+    assert_eq!(parsed.cycles(), 1000);
+    assert_eq!(parsed.timing(), Timing::Vip);
+    assert_eq!(parsed.fault_policy(), policy);
+    assert_eq!(parsed.keys(1), keys);
+    assert_eq!(parsed.keys(2), [false; 16]);
+    assert!(parsed.check(&program).is_ok());
+    assert_eq!(
+      parsed.check(&[0x13, 0x00]),
+      Err(MovieError::RomMismatch { movie: rom_hash(&program), running: rom_hash(&[0x13, 0x00]) })
+    );
+  }
+
+  #[test]
+  fn test_record_after_rewind() {
+    let mut movie = Movie::new(&[], Quirks::default(), 0, 700, Timing::Flat, FaultPolicy::HALT);
+    for frame in 0..5 {
+      movie.record(frame, &[false; 16]);
+    }
+    movie.record(2, &[true; 16]);
+    assert_eq!(movie.len(), 3);
+    assert_eq!(movie.keys(2), [true; 16]);
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert_eq!(Movie::parse("something"), Err(MovieError::InvalidHeader));
+    assert_eq!(Movie::parse("ferret-8 movie 2"), Err(MovieError::UnsupportedVersion(2)));
+    let text = "ferret-8 movie 1\nrom 00\nquirks 00\nseed 1\ntiming flat\nframes\n0000\n";
+    assert_eq!(Movie::parse(text), Err(MovieError::MissingField("cycles")));
+    let text = "ferret-8 movie 1\nrom 00\nquirks 00\nseed 1\ncycles 700\ntiming fast\n";
+    assert_eq!(Movie::parse(text), Err(MovieError::InvalidLine(6)));
+    let text = "ferret-8 movie 1\nrom 00\nquirks 00\nseed 1\ncycles 7\ntiming vip\nframes\nzz\n";
+    assert_eq!(Movie::parse(text), Err(MovieError::InvalidLine(8)));
+    let text = "ferret-8 movie 1\nrom 00\nquirks 00\nseed 1\ncycles 7\ntiming vip\nframes\n";
+    assert_eq!(Movie::parse(text), Err(MovieError::MissingField("faults")));
+    let text = "ferret-8 movie 1\nrom 00\nquirks 00\nseed 1\nfaults stack=skip\n";
+    assert_eq!(Movie::parse(text), Err(MovieError::InvalidLine(5)));
+    assert_ne!(rom_hash(&[1]), rom_hash(&[2]));
+  }
+}
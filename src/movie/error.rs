@@ -0,0 +1,39 @@
+//! error.rs
+//! Possible errors reading or playing an input movie.
+
+use std::{error::Error, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieError {
+  /// The first line is not a ferret-8 movie header.
+  InvalidHeader,
+  UnsupportedVersion(u32),
+  /// The line (1-based) could not be parsed.
+  InvalidLine(usize),
+  /// A header field is not present.
+  MissingField(&'static str),
+  /// The movie was recorded with another program: the hash in the movie, and the one of the
+  /// program running.
+  RomMismatch {
+    movie: u64,
+    running: u64,
+  },
+}
+
+impl fmt::Display for MovieError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      | Self::InvalidHeader => write!(f, "Not a ferret-8 movie"),
+      | Self::UnsupportedVersion(v) => write!(f, "Unsupported movie version: {}", v),
+      | Self::InvalidLine(line) => write!(f, "Line {}: invalid movie line", line),
+      | Self::MissingField(field) => write!(f, "Movie without the {} field", field),
+      | Self::RomMismatch { movie, running } => write!(
+        f,
+        "Movie recorded with another program (hash {:016x}, running {:016x})",
+        movie, running
+      ),
+    }
+  }
+}
+
+impl Error for MovieError {}
@@ -0,0 +1,168 @@
+//! rewind.rs
+//! Ring buffer of the last frames, to step the emulator backwards.
+
+use std::collections::VecDeque;
+
+use crate::emulator::{Emulator, StateError};
+use crate::rng::SerializableRng;
+
+/// Bounded history of save states, one per frame.
+///
+/// Only the newest state is kept whole. Each older frame is stored as the XOR between it and the
+/// next one, with the runs of zeros compressed: between two frames only a few bytes change, so
+/// each delta is tiny compared to the full state.
+#[derive(Debug, Default)]
+pub struct Rewind {
+  capacity: usize,
+  current: Option<Vec<u8>>,
+  /// deltas[n] turns the frame n + 1 into the frame n. The back is the closest to current.
+  deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+  /// Creates an empty history holding up to capacity frames back.
+  pub fn new(capacity: usize) -> Self {
+    Self { capacity, current: None, deltas: VecDeque::with_capacity(capacity) }
+  }
+
+  /// Number of frames that can be stepped back.
+  pub fn len(&self) -> usize {
+    self.deltas.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.deltas.is_empty()
+  }
+
+  /// Bytes used by the stored frames.
+  pub fn size(&self) -> usize {
+    self.current.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+  }
+
+  /// Forget every frame.
+  pub fn clear(&mut self) {
+    self.current = None;
+    self.deltas.clear();
+  }
+
+  /// Capture the state of the emulator (and its random generator) as the newest frame,
+  /// dropping the oldest if full.
+  pub fn push<R: SerializableRng>(&mut self, emu: &Emulator, rng: &R) {
+    if self.capacity == 0 {
+      return;
+    }
+    let state = emu.save_state_with_rng(rng);
+    if let Some(current) = self.current.take() {
+      if self.deltas.len() == self.capacity {
+        self.deltas.pop_front();
+      }
+      self.deltas.push_back(delta(&state, &current));
+    }
+    self.current = Some(state);
+  }
+
+  /// Restore the emulator to the previous frame. Return false if there is none left.
+  pub fn step_back<R: SerializableRng>(
+    &mut self, emu: &mut Emulator, rng: &mut R,
+  ) -> Result<bool, StateError> {
+    let (Some(current), Some(delta)) = (self.current.as_mut(), self.deltas.pop_back()) else {
+      return Ok(false);
+    };
+    apply(current, &delta);
+    emu.load_state_with_rng(current, rng)?;
+    Ok(true)
+  }
+}
+
+/// Encode the changes to turn from into to: the length of to (u32), and then blocks of
+/// zeros to skip (u32), literal length (u32) and the literal XORed bytes.
+fn delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+  let mut out = Vec::new();
+  out.extend_from_slice(&(to.len() as u32).to_le_bytes());
+  let xor = |n: usize| from.get(n).copied().unwrap_or(0) ^ to.get(n).copied().unwrap_or(0);
+  let len = from.len().max(to.len());
+  let mut n = 0;
+  while n < len {
+    let start = n;
+    while n < len && xor(n) == 0 {
+      n += 1;
+    }
+    if n == len {
+      break;
+    }
+    let skip = n - start;
+    let literal = n;
+    while n < len && xor(n) != 0 {
+      n += 1;
+    }
+    out.extend_from_slice(&(skip as u32).to_le_bytes());
+    out.extend_from_slice(&((n - literal) as u32).to_le_bytes());
+    out.extend((literal..n).map(xor));
+  }
+  out
+}
+
+/// Turn state into the one encoded by delta.
+fn apply(state: &mut Vec<u8>, delta: &[u8]) {
+  let read = |pos: usize| u32::from_le_bytes(delta[pos..pos + 4].try_into().unwrap()) as usize;
+  let len = read(0);
+  state.resize(state.len().max(len), 0);
+  let mut pos = 4;
+  let mut n = 0;
+  while pos < delta.len() {
+    n += read(pos);
+    let literal = read(pos + 4);
+    pos += 8;
+    for byte in &delta[pos..pos + literal] {
+      state[n] ^= byte;
+      n += 1;
+    }
+    pos += literal;
+  }
+  state.truncate(len);
+}
+
+#[cfg(test)]
+mod test {
+  use crate::emulator::{Emulator, Quirks};
+  use crate::rewind::{Rewind, apply, delta};
+  use crate::rng::SeededRng;
+
+  #[test]
+  fn test_delta() {
+    let a = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    let b = vec![1, 2, 9, 4, 5, 6, 0, 0, 0, 1];
+    let mut state = a.clone();
+    apply(&mut state, &delta(&a, &b));
+    assert_eq!(state, b);
+    apply(&mut state, &delta(&b, &a));
+    assert_eq!(state, a);
+    // Only the length and one block for one changed byte.
+    assert_eq!(delta(&a, &[1, 2, 3, 4, 0, 6, 7, 8]).len(), 4 + 8 + 1);
+  }
+
+  #[test]
+  fn test_step_back() {
+    let mut emu = Emulator::new(Quirks::COSMAC_VIP);
+    emu.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    let mut rng = SeededRng::new(1);
+    let mut rewind = Rewind::new(3);
+    rewind.push(&emu, &rng);
+    for _ in 0..5 {
+      for _ in 0..2 {
+        let instr = crate::decoder::decode(emu.fetch().unwrap()).unwrap();
+        emu.execute(instr, &mut rng, &[false; 16]).unwrap();
+      }
+      rewind.push(&emu, &rng);
+    }
+    assert_eq!(emu.registers()[0], 5);
+    assert_eq!(rewind.len(), 3);
+    assert!(rewind.size() < emu.save_state_with_rng(&rng).len() + 100);
+    for value in [4, 3, 2] {
+      assert!(rewind.step_back(&mut emu, &mut rng).unwrap());
+      assert_eq!(emu.registers()[0], value);
+    }
+    assert!(!rewind.step_back(&mut emu, &mut rng).unwrap());
+    assert_eq!(emu.registers()[0], 2);
+  }
+}
@@ -0,0 +1,117 @@
+//! rng.rs
+//! Seedable random number generator, to make the runs reproducible.
+
+use std::convert::Infallible;
+
+use rand::TryRng;
+
+/// A random generator that can be stored, to continue its sequence after restoring it (in save
+/// states, next to the emulator).
+pub trait SerializableRng: Sized {
+  /// Serialize the generator, in its current position of the sequence.
+  fn save(&self) -> Vec<u8>;
+
+  /// Restore a generator from the bytes of save. None if they are not valid.
+  fn restore(data: &[u8]) -> Option<Self>;
+}
+
+/// SplitMix64 generator. Small and fast, and its whole state is a single number, so it can be
+/// stored in save states and restored to continue the same sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeededRng {
+  seed: u64,
+  state: u64,
+}
+
+impl SeededRng {
+  /// Creates a generator at the start of the sequence of the seed.
+  pub fn new(seed: u64) -> Self {
+    Self { seed, state: seed }
+  }
+
+  /// Creates a generator with a random seed.
+  pub fn from_entropy() -> Self {
+    Self::new(rand::random())
+  }
+
+  /// Restore a generator in the middle of a sequence, from its seed and state.
+  pub fn from_parts(seed: u64, state: u64) -> Self {
+    Self { seed, state }
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  /// Current position in the sequence.
+  pub fn state(&self) -> u64 {
+    self.state
+  }
+
+  fn next(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+}
+
+/// Stored as the seed and the state (u64 each, in little endian).
+impl SerializableRng for SeededRng {
+  fn save(&self) -> Vec<u8> {
+    [self.seed.to_le_bytes(), self.state.to_le_bytes()].concat()
+  }
+
+  fn restore(data: &[u8]) -> Option<Self> {
+    let (seed, state) = data.split_first_chunk::<8>()?;
+    let state: [u8; 8] = state.try_into().ok()?;
+    Some(Self::from_parts(u64::from_le_bytes(*seed), u64::from_le_bytes(state)))
+  }
+}
+
+impl TryRng for SeededRng {
+  type Error = Infallible;
+
+  fn try_next_u32(&mut self) -> Result<u32, Self::Error> {
+    Ok((self.next() >> 32) as u32)
+  }
+
+  fn try_next_u64(&mut self) -> Result<u64, Self::Error> {
+    Ok(self.next())
+  }
+
+  fn try_fill_bytes(&mut self, dst: &mut [u8]) -> Result<(), Self::Error> {
+    for chunk in dst.chunks_mut(8) {
+      let bytes = self.next().to_le_bytes();
+      chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::rng::{SeededRng, SerializableRng};
+
+  use rand::RngExt;
+
+  #[test]
+  fn test_reproducible() {
+    let mut a = SeededRng::new(42);
+    let mut b = SeededRng::new(42);
+    let first: Vec<u8> = (0..32).map(|_| a.random()).collect();
+    let second: Vec<u8> = (0..32).map(|_| b.random()).collect();
+    assert_eq!(first, second);
+    assert_ne!(first, (0..32).map(|_| SeededRng::new(43).random()).collect::<Vec<u8>>());
+
+    // Restoring from the parts continues the same sequence.
+    let mut c = SeededRng::from_parts(a.seed(), a.state());
+    assert_eq!(a.random::<u64>(), c.random::<u64>());
+    assert_eq!(c.seed(), 42);
+
+    let mut d = SeededRng::restore(&c.save()).unwrap();
+    assert_eq!(c.random::<u64>(), d.random::<u64>());
+    assert_eq!(SeededRng::restore(&[0; 15]), None);
+  }
+}
@@ -0,0 +1,207 @@
+//! scheduler.rs
+//! Keeps the emulation in step with the real time, independent of the frame rate.
+
+use std::time::Duration;
+
+use crate::decoder::Instruction;
+use crate::emulator::{Emulator, TIMER_FREQUENCY};
+use crate::timing::{Timing, VIP_AVAILABLE_CYCLES, vip_cycles};
+
+/// Most ticks emulated at once after a stall (a quarter of second). The rest of the time is
+/// dropped, instead of running the program at full speed to catch up.
+pub const MAX_CATCH_UP_TICKS: u32 = TIMER_FREQUENCY / 4;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Splits the real time in ticks of the timers (60 per second), and gives each tick a budget of
+/// instructions to execute.
+///
+/// With the flat timing, the budget is the instructions per second between the ticks, each
+/// instruction costing 1. Everything is counted in integers, carrying the remainders over:
+/// 700 instructions per second run 11 or 12 per tick, exactly 700 each second, and the ticks
+/// never drift.
+///
+/// With the VIP timing, the budget is the machine cycles of a VIP frame, and each instruction
+/// costs its VIP cycles. An instruction that goes over the budget takes the cycles from the next
+/// tick.
+///
+/// ```ignore
+/// scheduler.start_tick();
+/// while scheduler.has_budget() {
+///   let instr = decode(emu.fetch()?)?;
+///   scheduler.charge(&instr, emu);
+///   emu.execute(instr, rng, &keys)?;
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+  timing: Timing,
+  cycles_per_second: u64,
+  /// Instructions owed to the next ticks, times TIMER_FREQUENCY.
+  cycle_remainder: u64,
+  /// Cost left in the current tick. Negative when an instruction went over it.
+  budget: i64,
+  /// Time not emulated yet, in nanoseconds times TIMER_FREQUENCY.
+  pending: u128,
+}
+
+impl Scheduler {
+  /// Creates a scheduler. The cycles per second are only used with the flat timing.
+  pub fn new(cycles_per_second: usize, timing: Timing) -> Self {
+    Self {
+      timing,
+      cycles_per_second: cycles_per_second as u64,
+      cycle_remainder: 0,
+      budget: 0,
+      pending: 0,
+    }
+  }
+
+  /// Timing the cost of the instructions is measured with.
+  pub fn timing(&self) -> Timing {
+    self.timing
+  }
+
+  /// Add the real time elapsed, and return the number of ticks to emulate for it.
+  ///
+  /// After a stall longer than MAX_CATCH_UP_TICKS, only that many ticks are returned.
+  pub fn advance(&mut self, elapsed: Duration) -> u32 {
+    self.pending += elapsed.as_nanos() * TIMER_FREQUENCY as u128;
+    let ticks = self.pending / NANOS_PER_SECOND;
+    self.pending %= NANOS_PER_SECOND;
+    if ticks > MAX_CATCH_UP_TICKS as u128 {
+      self.pending = 0;
+      return MAX_CATCH_UP_TICKS;
+    }
+    ticks as u32
+  }
+
+  /// Start a new tick, with a new budget. The budget not spent in the previous tick (because the
+  /// program exited or hit a breakpoint) is lost, but the cost over it is kept.
+  pub fn start_tick(&mut self) {
+    let budget = match self.timing {
+      | Timing::Flat => {
+        self.cycle_remainder += self.cycles_per_second;
+        let cycles = self.cycle_remainder / TIMER_FREQUENCY as u64;
+        self.cycle_remainder %= TIMER_FREQUENCY as u64;
+        cycles as i64
+      },
+      | Timing::Vip => VIP_AVAILABLE_CYCLES as i64,
+    };
+    self.budget = self.budget.min(0) + budget;
+  }
+
+  /// Return true if there is budget left for another instruction in this tick.
+  pub fn has_budget(&self) -> bool {
+    self.budget > 0
+  }
+
+  /// Spend the cost of the instruction, about to be executed.
+  ///
+  /// With the display_wait quirk, Dxyn waits for the vertical blank: it also spends the rest of
+  /// the tick, so at most one sprite is drawn per tick.
+  pub fn charge(&mut self, instr: &Instruction, emu: &Emulator) {
+    self.budget -= match self.timing {
+      | Timing::Flat => 1,
+      | Timing::Vip => vip_cycles(instr, emu) as i64,
+    };
+    if matches!(instr, Instruction::Display(..)) && emu.quirks().display_wait {
+      self.budget = self.budget.min(0);
+    }
+  }
+
+  /// Forget the time not emulated yet, like after a pause.
+  pub fn reset(&mut self) {
+    self.pending = 0;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::time::Duration;
+
+  use crate::decoder::Instruction;
+  use crate::emulator::{Emulator, Quirks};
+  use crate::scheduler::{MAX_CATCH_UP_TICKS, Scheduler};
+  use crate::timing::Timing;
+
+  /// Instructions run in each tick, all of them the instruction.
+  fn run_ticks(scheduler: &mut Scheduler, ticks: usize, instr: Instruction) -> Vec<usize> {
+    run_ticks_with(scheduler, ticks, instr, Quirks::CHIP_48)
+  }
+
+  fn run_ticks_with(
+    scheduler: &mut Scheduler, ticks: usize, instr: Instruction, quirks: Quirks,
+  ) -> Vec<usize> {
+    let emu = Emulator::new(quirks);
+    let mut counts = Vec::new();
+    for _ in 0..ticks {
+      scheduler.start_tick();
+      let mut count = 0;
+      while scheduler.has_budget() {
+        scheduler.charge(&instr, &emu);
+        count += 1;
+      }
+      counts.push(count);
+    }
+    counts
+  }
+
+  #[test]
+  fn test_flat() {
+    let mut scheduler = Scheduler::new(700, Timing::Flat);
+    let counts = run_ticks(&mut scheduler, 60, Instruction::Cls);
+    assert_eq!(counts.iter().sum::<usize>(), 700);
+    assert!(counts.iter().all(|c| *c == 11 || *c == 12));
+
+    let mut scheduler = Scheduler::new(30, Timing::Flat);
+    assert_eq!(run_ticks(&mut scheduler, 4, Instruction::Cls), [0, 1, 0, 1]);
+  }
+
+  #[test]
+  fn test_vip() {
+    let mut scheduler = Scheduler::new(700, Timing::Vip);
+    // 46 cycles each.
+    let counts = run_ticks(&mut scheduler, 2, Instruction::LoadInmm(0, 0));
+    assert_eq!(counts, [57, 56]);
+    // Slow instructions run fewer times.
+    let mut scheduler = Scheduler::new(700, Timing::Vip);
+    let counts = run_ticks(&mut scheduler, 1, Instruction::Display(0, 0, 15));
+    assert_eq!(counts, [4]);
+  }
+
+  #[test]
+  fn test_display_wait() {
+    let display = Instruction::Display(0, 0, 5);
+    let mut scheduler = Scheduler::new(700, Timing::Flat);
+    assert_eq!(run_ticks_with(&mut scheduler, 3, display, Quirks::COSMAC_VIP), [1, 1, 1]);
+    let mut scheduler = Scheduler::new(700, Timing::Vip);
+    assert_eq!(run_ticks_with(&mut scheduler, 3, display, Quirks::COSMAC_VIP), [1, 1, 1]);
+    // Other instructions are not affected.
+    let mut scheduler = Scheduler::new(700, Timing::Flat);
+    let counts = run_ticks_with(&mut scheduler, 1, Instruction::Cls, Quirks::COSMAC_VIP);
+    assert_eq!(counts, [11]);
+  }
+
+  #[test]
+  fn test_advance() {
+    let mut scheduler = Scheduler::new(700, Timing::Flat);
+    // Frames slightly faster and slower than 60 Hz, still 60 ticks per second.
+    let mut ticks = 0;
+    for n in 0..100 {
+      ticks += scheduler.advance(Duration::from_millis(if n % 2 == 0 { 15 } else { 5 }));
+    }
+    assert_eq!(ticks, 60);
+
+    assert_eq!(scheduler.advance(Duration::from_micros(16_000)), 0);
+    assert_eq!(scheduler.advance(Duration::from_micros(1_000)), 1);
+
+    // A stall of 2 seconds only runs a quarter of second.
+    assert_eq!(scheduler.advance(Duration::from_secs(2)), MAX_CATCH_UP_TICKS);
+    assert_eq!(scheduler.advance(Duration::from_millis(10)), 0);
+
+    scheduler.advance(Duration::from_millis(10));
+    scheduler.reset();
+    assert_eq!(scheduler.advance(Duration::from_millis(10)), 0);
+  }
+}
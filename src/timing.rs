@@ -0,0 +1,131 @@
+//! timing.rs
+//! Cost of each instruction in the original COSMAC VIP interpreter.
+
+use crate::decoder::Instruction;
+use crate::emulator::Emulator;
+
+/// Machine cycles (8 clock cycles of the 1.76 MHz 1802) in each 60 Hz frame.
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+/// Machine cycles of each frame taken by the display: the DMA of the 128 video lines and the
+/// interrupt routine. The interpreter only runs in the rest.
+pub const VIP_DISPLAY_CYCLES: u32 = 1024 + 46;
+/// Machine cycles left to the interpreter each frame.
+pub const VIP_AVAILABLE_CYCLES: u32 = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+
+/// Machine cycles of fetching and decoding any instruction, before executing it. An estimate:
+/// the source of vip_cycles only measures the execution.
+const FETCH_CYCLES: u32 = 40;
+
+/// How the time of the emulated machine is measured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+  /// Every instruction takes the same time, at a fixed number per second.
+  #[default]
+  Flat,
+  /// Every instruction takes the machine cycles it took in the COSMAC VIP, and each frame runs
+  /// VIP_AVAILABLE_CYCLES of them.
+  Vip,
+}
+
+/// Machine cycles the instruction takes in the VIP interpreter, fetch included.
+///
+/// The execution times are the ones measured by Jackson Sommerich in "Chip-8 Instruction
+/// Scheduling and Frequency" (2019), in microseconds, converted at 4.54 us per machine cycle
+/// (8 clock cycles at 1.7609 MHz) and rounded. The instructions with a variable time (the skips,
+/// Fx33, Fx55 and Fx65) take the average measured.
+///
+/// Not measured there, and estimated instead: the fetch, Dxyn (the measure includes the wait for
+/// the display interrupt), Fx0A and the instructions not in the VIP. Dxyn depends on the height of
+/// the sprite and whether it is aligned to a byte, so it has to be called before executing the
+/// instruction.
+pub fn vip_cycles(instr: &Instruction, emu: &Emulator) -> u32 {
+  let v = emu.registers();
+  let cycles = match *instr {
+    | Instruction::Cls => 24,
+    | Instruction::Return => 23,
+    | Instruction::SetPC(_) => 23,
+    | Instruction::Call(_) => 23,
+    | Instruction::SeInmm(..) | Instruction::SneInmm(..) => 12,
+    | Instruction::SeReg(..) | Instruction::SneReg(..) => 16,
+    | Instruction::LoadInmm(_, _) => 6,
+    | Instruction::Sum(_, _) => 10,
+    | Instruction::LoadReg(_, _)
+    | Instruction::Or(_, _)
+    | Instruction::And(_, _)
+    | Instruction::Xor(_, _)
+    | Instruction::Add(_, _)
+    | Instruction::Sub(_, _)
+    | Instruction::ShiftRight(_, _)
+    | Instruction::SubRev(_, _)
+    | Instruction::ShiftLeft(_, _) => 44,
+    | Instruction::LoadI(_) => 12,
+    | Instruction::Jump(_) => 23,
+    | Instruction::Rand(_, _) => 36,
+    // Each row is shifted into place, and takes two bytes if not aligned.
+    | Instruction::Display(x, _, n) => {
+      let aligned = v[x].is_multiple_of(8);
+      68 + n as u32 * if aligned { 46 } else { 66 }
+    },
+    | Instruction::Skip(_) | Instruction::Snkip(_) => 16,
+    // Each poll of the keyboard, repeated while no key is pressed.
+    | Instruction::WaitKey(_) => 10,
+    | Instruction::GetDelay(_) | Instruction::LoadDelay(_) | Instruction::LoadSound(_) => 10,
+    | Instruction::AddI(_) => 19,
+    | Instruction::LoadFont(_) => 20,
+    | Instruction::Bcd(_) => 204,
+    | Instruction::StMem(_) | Instruction::LdMem(_) => 133,
+    // Not in the VIP: the cost of a similar instruction.
+    | Instruction::ScrollDown(_)
+    | Instruction::ScrollUp(_)
+    | Instruction::ScrollRight
+    | Instruction::ScrollLeft => 24,
+    | Instruction::Exit | Instruction::Lores | Instruction::Hires => 10,
+    // The cost of the machine code is unknown, only the call is counted.
+    | Instruction::Sys(_) => 10,
+    | Instruction::StRange(..) | Instruction::LdRange(..) => 133,
+    | Instruction::LoadLongI => 24,
+    | Instruction::Plane(_) | Instruction::Pitch(_) => 10,
+    | Instruction::LoadAudio => 133,
+    | Instruction::LoadBigFont(_) => 20,
+    | Instruction::StFlags(_) | Instruction::LdFlags(_) => 133,
+  };
+  FETCH_CYCLES + cycles
+}
+
+#[cfg(test)]
+mod test {
+  use crate::decoder::Instruction;
+  use crate::emulator::{Emulator, Quirks};
+  use crate::timing::vip_cycles;
+
+  #[test]
+  fn test_vip_cycles() {
+    let mut emu = Emulator::new(Quirks::COSMAC_VIP);
+    let mut rng = rand::rng();
+    emu.execute(Instruction::LoadInmm(0, 8), &mut rng, &[false; 16]).unwrap();
+    emu.execute(Instruction::LoadInmm(1, 3), &mut rng, &[false; 16]).unwrap();
+
+    // The measured times, in microseconds, and the fetch.
+    let cycles = |instr| vip_cycles(&instr, &emu);
+    assert_eq!(cycles(Instruction::Cls), 40 + 24); // 109 us
+    assert_eq!(cycles(Instruction::Return), 40 + 23); // 105 us
+    assert_eq!(cycles(Instruction::SetPC(0x200)), 40 + 23); // 105 us
+    assert_eq!(cycles(Instruction::SeInmm(0, 8)), 40 + 12); // 55 us
+    assert_eq!(cycles(Instruction::SneReg(0, 1)), 40 + 16); // 73 us
+    assert_eq!(cycles(Instruction::LoadInmm(2, 1)), 40 + 6); // 27 us
+    assert_eq!(cycles(Instruction::Sum(2, 1)), 40 + 10); // 45 us
+    assert_eq!(cycles(Instruction::Xor(0, 1)), 40 + 44); // 200 us
+    assert_eq!(cycles(Instruction::LoadI(0x300)), 40 + 12); // 55 us
+    assert_eq!(cycles(Instruction::Rand(0, 0xFF)), 40 + 36); // 164 us
+    assert_eq!(cycles(Instruction::Skip(0)), 40 + 16); // 73 us
+    assert_eq!(cycles(Instruction::GetDelay(0)), 40 + 10); // 45 us
+    assert_eq!(cycles(Instruction::AddI(0)), 40 + 19); // 86 us
+    assert_eq!(cycles(Instruction::LoadFont(0)), 40 + 20); // 91 us
+    assert_eq!(cycles(Instruction::Bcd(0)), 40 + 204); // 927 us
+    assert_eq!(cycles(Instruction::StMem(15)), 40 + 133); // 605 us
+
+    // Estimated: taller and unaligned sprites cost more.
+    assert_eq!(cycles(Instruction::Display(0, 0, 5)), 40 + 68 + 5 * 46);
+    assert_eq!(cycles(Instruction::Display(1, 0, 5)), 40 + 68 + 5 * 66);
+  }
+}
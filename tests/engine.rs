@@ -0,0 +1,155 @@
+//! engine.rs
+//! Runs the interpreter and the block engine in lockstep with a plain fetch, decode and execute
+//! loop, checking that the whole state of the emulator is the same after every block.
+
+use std::{fs, path::PathBuf};
+
+use ferret_8::assembler::assemble;
+use ferret_8::decoder::decode;
+use ferret_8::emulator::{Emulator, Quirks};
+use ferret_8::engine::{Engine, Executor};
+use ferret_8::headless::{KeyScript, KeySource};
+use ferret_8::rng::SeededRng;
+use ferret_8::scheduler::Scheduler;
+use ferret_8::timing::Timing;
+
+/// Instructions per second, as in the Timendus tests.
+const CYCLES: usize = 1000;
+
+/// An emulator with its own scheduler and random generator.
+struct Machine {
+  emu: Emulator,
+  rng: SeededRng,
+  scheduler: Scheduler,
+}
+
+impl Machine {
+  fn new(program: &[u8], quirks: Quirks) -> Self {
+    let mut emu = Emulator::new(quirks);
+    emu.load_program(program).unwrap();
+    Self { emu, rng: SeededRng::new(0), scheduler: Scheduler::new(CYCLES, Timing::Flat) }
+  }
+
+  /// Fetch, decode and execute an instruction by hand, without the cache of decoded instructions
+  /// both engines are built on.
+  fn reference_step(&mut self, keys: &[bool]) {
+    let instr = decode(self.emu.fetch().unwrap()).unwrap();
+    self.scheduler.charge(&instr, &self.emu);
+    self.emu.execute(instr, &mut self.rng, keys).unwrap();
+  }
+
+  fn engine_step(&mut self, executor: &mut Executor, keys: &[bool]) -> usize {
+    executor.step(&mut self.emu, &mut self.rng, keys, &mut self.scheduler).unwrap()
+  }
+
+  fn state(&self) -> Vec<u8> {
+    self.emu.save_state_with_rng(&self.rng)
+  }
+}
+
+/// Run the program a block at a time with the block engine, and then as many instructions with
+/// the interpreter and with plain fetch, decode and execute, comparing their states.
+fn run_lockstep(name: &str, program: &[u8], quirks: Quirks, frames: u64, keys: &str) {
+  let script = KeyScript::parse(keys).unwrap();
+  let mut reference = Machine::new(program, quirks);
+  let mut interp = Machine::new(program, quirks);
+  let mut block = Machine::new(program, quirks);
+  let mut interp_executor = Executor::new(Engine::Interp);
+  let mut block_executor = Executor::new(Engine::Block);
+  for frame in 0..frames {
+    let keys = script.keys(frame);
+    for machine in [&mut reference, &mut interp, &mut block] {
+      machine.emu.decrease_timers();
+      machine.scheduler.start_tick();
+    }
+    while block.scheduler.has_budget() && !block.emu.has_exited() {
+      let pc = block.emu.reg_pc();
+      let executed = block.engine_step(&mut block_executor, &keys);
+      for _ in 0..executed {
+        assert!(reference.scheduler.has_budget());
+        reference.reference_step(&keys);
+        assert_eq!(interp.engine_step(&mut interp_executor, &keys), 1);
+      }
+      let expected = reference.state();
+      for (engine, machine) in [("interpreter", &interp), ("block engine", &block)] {
+        assert!(
+          machine.state() == expected,
+          "{}: the {} differs after the block in 0x{:03X} ({} instructions), frame {}",
+          name,
+          engine,
+          pc,
+          executed,
+          frame
+        );
+      }
+    }
+    assert_eq!(reference.scheduler.has_budget(), block.scheduler.has_budget());
+  }
+}
+
+/// Run a ROM of Timendus' test suite in lockstep. A missing ROM fails the test.
+fn run_rom(rom: &str, quirks: Quirks, frames: u64, keys: &str) {
+  let path = [env!("CARGO_MANIFEST_DIR"), "tests", "roms", rom].iter().collect::<PathBuf>();
+  let Ok(program) = fs::read(&path) else {
+    panic!("{} not found (see tests/roms/README.md)", path.display());
+  };
+  run_lockstep(rom, &program, quirks, frames, keys);
+}
+
+/// Ignored by default, as the tests in timendus.rs: the ROMs are not distributed.
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_roms() {
+  run_rom("2-ibm-logo.ch8", Quirks::COSMAC_VIP, 60, "");
+  run_rom("3-corax+.ch8", Quirks::COSMAC_VIP, 120, "");
+  run_rom("4-flags.ch8", Quirks::COSMAC_VIP, 120, "");
+  run_rom("5-quirks.ch8", Quirks::COSMAC_VIP, 600, "60 1\n70\n");
+  run_rom("5-quirks.ch8", Quirks::SCHIP, 600, "60 2\n70\n");
+  run_rom("5-quirks.ch8", Quirks::XO_CHIP, 600, "60 3\n70\n");
+  run_rom("6-keypad.ch8", Quirks::COSMAC_VIP, 180, "60 1\n70\n120 5 A\n");
+}
+
+/// Writes over its own code, in the same block, in other blocks and with BCD, and calls, skips,
+/// draws and waits for keys.
+const SELF_MODIFYING: &str = "
+start:  LD V1, 0
+        LD V5, 0
+loop:   ADD V1, 1
+        LD V0, 0x62
+        LD I, patch
+        LD [I], V1
+patch:  LD V2, 0
+        ADD V5, V2
+        CALL draw
+        LD I, digits
+        LD B, V5
+        JP next
+digits: DB 0, 0, 0, 0
+next:   LD V3, V1
+        LD I, target
+        LD V0, 0x63
+        LD [I], V1
+        SNE V1, 40
+        LD V4, K
+        SE V1, 80
+        JP loop
+        JP start
+draw:   LD V8, 0x0F
+        AND V8, V2
+        LD F, V8
+        RND V6, 0x3F
+        DRW V6, V5, 5
+        LD DT, V1
+        LD V7, DT
+        SKP V8
+target: LD V3, 0
+        RET
+";
+
+#[test]
+fn test_self_modifying() {
+  let program = assemble(SELF_MODIFYING).unwrap();
+  for quirks in [Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SCHIP, Quirks::XO_CHIP] {
+    run_lockstep("self-modifying", &program, quirks, 300, "50 5\n60\n");
+  }
+}
@@ -0,0 +1,155 @@
+//! timendus.rs
+//! Runs the ROMs of Timendus' CHIP-8 test suite headless, comparing the final display against the
+//! golden framebuffers in tests/golden.
+//!
+//! The suite is GPL-3.0, so its ROMs can't be distributed with ferret-8, and the tests are
+//! ignored by default. Copy the ROMs into tests/roms and run them with --ignored.
+
+use std::{env, fs, path::PathBuf};
+
+use ferret_8::emulator::{Emulator, Quirks};
+use ferret_8::engine::{Engine, Executor};
+use ferret_8::headless::{self, KeyScript};
+use ferret_8::rng::SeededRng;
+use ferret_8::scheduler::Scheduler;
+use ferret_8::timing::Timing;
+
+/// Instructions per second, fast enough for every test to finish in its frames.
+const CYCLES: usize = 1000;
+
+/// One run of a test ROM.
+struct Case {
+  /// Name of the golden framebuffer.
+  name: &'static str,
+  /// File in tests/roms.
+  rom: &'static str,
+  quirks: Quirks,
+  frames: u64,
+  /// Keys held, in the format of KeyScript.
+  keys: &'static str,
+}
+
+fn manifest_path(parts: &[&str]) -> PathBuf {
+  parts.iter().fold(PathBuf::from(env!("CARGO_MANIFEST_DIR")), |path, part| path.join(part))
+}
+
+/// Run the case and compare its display with the golden one.
+///
+/// A missing ROM fails the test. With FERRET_BLESS set, the golden is written instead.
+fn run_case(case: &Case) {
+  let rom_path = manifest_path(&["tests", "roms", case.rom]);
+  let Ok(rom) = fs::read(&rom_path) else {
+    panic!("{}: {} not found (see tests/roms/README.md)", case.name, rom_path.display());
+  };
+
+  let mut emu = Emulator::new(case.quirks);
+  emu.load_program(&rom).unwrap();
+  let script = KeyScript::parse(case.keys).unwrap();
+  let scheduler = Scheduler::new(CYCLES, Timing::Flat);
+  let executor = Executor::new(Engine::Interp);
+  let mut rng = SeededRng::new(0);
+  headless::run_frames(&mut emu, &mut rng, case.frames, scheduler, executor, &script).unwrap();
+  let display = headless::display_ascii(&emu);
+
+  let golden_path = manifest_path(&["tests", "golden", &format!("{}.txt", case.name)]);
+  if env::var_os("FERRET_BLESS").is_some() {
+    fs::write(&golden_path, &display).unwrap();
+    return;
+  }
+  let Ok(golden) = fs::read_to_string(&golden_path) else {
+    panic!("No golden for {}, run with FERRET_BLESS=1 to write it:\n{}", case.name, display);
+  };
+  assert!(
+    golden == display,
+    "{} differs from its golden.\nExpected:\n{}\nFound:\n{}",
+    case.name,
+    golden,
+    display
+  );
+}
+
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_ibm_logo() {
+  run_case(&Case {
+    name: "ibm-logo",
+    rom: "2-ibm-logo.ch8",
+    quirks: Quirks::COSMAC_VIP,
+    frames: 60,
+    keys: "",
+  });
+}
+
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_corax() {
+  run_case(&Case {
+    name: "corax+",
+    rom: "3-corax+.ch8",
+    quirks: Quirks::COSMAC_VIP,
+    frames: 120,
+    keys: "",
+  });
+}
+
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_flags() {
+  run_case(&Case {
+    name: "flags",
+    rom: "4-flags.ch8",
+    quirks: Quirks::COSMAC_VIP,
+    frames: 120,
+    keys: "",
+  });
+}
+
+/// The quirks test asks for the platform first: 1 CHIP-8, 2 SUPER-CHIP, 3 XO-CHIP.
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_quirks_chip8() {
+  run_case(&Case {
+    name: "quirks-chip8",
+    rom: "5-quirks.ch8",
+    quirks: Quirks::COSMAC_VIP,
+    frames: 600,
+    keys: "60 1\n70\n",
+  });
+}
+
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_quirks_schip() {
+  run_case(&Case {
+    name: "quirks-schip",
+    rom: "5-quirks.ch8",
+    quirks: Quirks::SCHIP,
+    frames: 600,
+    keys: "60 2\n70\n",
+  });
+}
+
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_quirks_xochip() {
+  run_case(&Case {
+    name: "quirks-xochip",
+    rom: "5-quirks.ch8",
+    quirks: Quirks::XO_CHIP,
+    frames: 600,
+    keys: "60 3\n70\n",
+  });
+}
+
+/// The keypad test asks for the instruction first (1 for Ex9E), then shows the keys held.
+#[test]
+#[ignore = "needs the ROMs of the suite in tests/roms"]
+fn test_keypad() {
+  run_case(&Case {
+    name: "keypad",
+    rom: "6-keypad.ch8",
+    quirks: Quirks::COSMAC_VIP,
+    frames: 180,
+    keys: "60 1\n70\n120 5 A\n",
+  });
+}